@@ -14,6 +14,10 @@ struct Args {
     
     #[arg(short, long)]
     otp: Option<String>,
+
+    /// Ask for the one-time password on stdin right before it is submitted
+    #[arg(long)]
+    prompt_otp: bool,
     
     #[arg(short, long)]
     region: Option<i8>,
@@ -55,15 +59,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Free trial: {}", args.free_trial);
     
     // Create HTTP client
-    let client = reqwest::Client::new();
+    let client = HttpClientConfig::default().build()?;
     
     // Build login request
-    let mut login_request = LoginRequest::new(client)
-        .with_username(args.username)
-        .with_password(args.password);
+    let mut login_request = LoginRequest::builder(client)
+        .credentials(args.username, args.password)
+        .with_event_handler(|event: AuthEvent| info!("{:?} after {:?}", event.stage, event.elapsed));
     
     if let Some(otp) = args.otp {
         login_request = login_request.with_otp(otp);
+    } else if args.prompt_otp {
+        login_request = login_request.with_otp_provider(StdinOtp);
     }
     
     if let Some(region) = args.region {
@@ -80,7 +86,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let global_client = GlobalClient::default();
     
     // Attempt authentication
-    match global_client.authenticate(login_request).await {
+    match global_client.login(login_request).await {
         Ok(response) => {
             info!("Authentication successful!");
             info!("Response: {:?}", response);
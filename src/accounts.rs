@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info, instrument};
+use crate::error::Error;
+use crate::prelude::{BoxedAuthProvider, ComputerId, HttpClientConfig, LoginRequest, LoginResponse, ProviderOptions, ProviderRegistry};
+
+/// Logs in many accounts from one process, keeping them apart: every account has its own HTTP
+/// client, cookies, computer ID, provider and session. A login returns the account's session
+/// while it is younger than the session TTL instead of logging in again, so frequent calls do
+/// not pile up logins on the account.
+///
+/// ```no_run
+/// # use ff_auth::prelude::*;
+/// # async fn run() -> Result<(), Error> {
+/// let mut manager = AccountManager::new().with_concurrency(8);
+/// manager.add_account("alice", "global", &ProviderOptions::default(), |req| {
+///     req.with_username("alice".to_string()).with_password("password")
+/// })?;
+/// for (name, res) in manager.login_all().await {
+///     println!("{name}: {:?}", res.map(|session| session.login_path()));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct AccountManager {
+    registry: ProviderRegistry,
+    client_config: HttpClientConfig,
+    concurrency: usize,
+    session_ttl: Duration,
+    accounts: HashMap<String, Arc<ManagedAccount>>,
+}
+
+struct ManagedAccount {
+    name: String,
+    provider_name: String,
+    provider: BoxedAuthProvider,
+    /// The request of every login, with the account's own HTTP client and computer ID.
+    request: LoginRequest,
+    session_ttl: Duration,
+    /// Held across a login, so concurrent logins of one account share its session.
+    session: tokio::sync::Mutex<Option<AccountSession>>,
+}
+
+/// The last successful login of a managed account.
+#[derive(Debug, Clone)]
+pub struct AccountSession {
+    pub response: LoginResponse,
+    pub logged_in_at: SystemTime,
+}
+
+impl Debug for AccountManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountManager")
+            .field("client_config", &self.client_config)
+            .field("concurrency", &self.concurrency)
+            .field("session_ttl", &self.session_ttl)
+            .field("accounts", &self.accounts.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Default for AccountManager {
+    fn default() -> Self {
+        AccountManager::new()
+    }
+}
+
+impl AccountManager {
+    pub const DEFAULT_CONCURRENCY: usize = 4;
+    pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+    pub fn new() -> Self {
+        AccountManager {
+            registry: ProviderRegistry::default(),
+            client_config: HttpClientConfig::default(),
+            concurrency: Self::DEFAULT_CONCURRENCY,
+            session_ttl: Self::DEFAULT_SESSION_TTL,
+            accounts: HashMap::new(),
+        }
+    }
+    /// The registry the providers of accounts added later are built from.
+    pub fn with_registry(mut self, registry: ProviderRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+    /// The configuration of the HTTP clients of accounts added later.
+    pub fn with_client_config(mut self, config: HttpClientConfig) -> Self {
+        self.client_config = config;
+        self
+    }
+    /// How many accounts [`AccountManager::login_all`] logs in at the same time, at least 1.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+    /// How long the session of accounts added later is reused, `Duration::ZERO` logs in every time.
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Adds or replaces the account `name`, logging in through the registry's provider `provider`.
+    /// `configure` sets up its requests, e.g. the credentials. Unless it sets a computer ID, the
+    /// account gets one derived from this machine and `name`, which stays the same across runs.
+    pub fn add_account<F>(&mut self, name: &str, provider: &str, options: &ProviderOptions, configure: F) -> crate::error::Result<()>
+    where F: FnOnce(LoginRequest) -> LoginRequest {
+        let mut request = configure(LoginRequest::new(self.client_config.build()?));
+        if request.computer_id.is_none() {
+            request.computer_id = Some(ComputerId::for_profile(name));
+        }
+
+        self.accounts.insert(name.to_string(), Arc::new(ManagedAccount {
+            name: name.to_string(),
+            provider_name: provider.to_string(),
+            provider: self.registry.build(provider, options)?,
+            request,
+            session_ttl: self.session_ttl,
+            session: tokio::sync::Mutex::new(None),
+        }));
+        Ok(())
+    }
+    pub fn remove_account(&mut self, name: &str) -> bool {
+        self.accounts.remove(name).is_some()
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &str> {
+        self.accounts.keys().map(String::as_str)
+    }
+    /// The registry name of the account's provider.
+    pub fn provider(&self, name: &str) -> Option<&str> {
+        self.accounts.get(name).map(|account| account.provider_name.as_str())
+    }
+    pub fn computer_id(&self, name: &str) -> Option<ComputerId> {
+        self.accounts.get(name).and_then(|account| account.request.computer_id)
+    }
+    /// The session of the account's last successful login, `None` while the account is logging in.
+    pub fn session(&self, name: &str) -> Option<AccountSession> {
+        self.accounts.get(name).and_then(|account| account.session.try_lock().ok()?.clone())
+    }
+    /// Drops the session of the account `name`, so its next login goes to the server, e.g. after
+    /// the server rejected the session.
+    pub async fn forget_session(&self, name: &str) {
+        if let Some(account) = self.accounts.get(name) {
+            *account.session.lock().await = None;
+        }
+    }
+
+    /// Returns the session of the account `name` while it is valid, or else logs in and keeps the
+    /// new session.
+    pub async fn login(&self, name: &str) -> crate::error::Result<LoginResponse> {
+        let account = self.accounts.get(name)
+            .ok_or_else(|| Error::UnknownAccount(name.to_string()))?;
+        account.login().await
+    }
+
+    /// Logs in every account, at most [`AccountManager::with_concurrency`] at a time.
+    pub async fn login_all(&self) -> HashMap<String, crate::error::Result<LoginResponse>> {
+        let permits = Arc::new(Semaphore::new(self.concurrency));
+        let mut logins = JoinSet::new();
+        let mut names = HashMap::new();
+        for account in self.accounts.values() {
+            let account = account.clone();
+            let permits = permits.clone();
+            let name = account.name.clone();
+            let handle = logins.spawn(async move {
+                let _permit = permits.acquire_owned().await.expect("the semaphore is never closed");
+                account.login().await
+            });
+            names.insert(handle.id(), name);
+        }
+
+        let mut results = HashMap::new();
+        while let Some(joined) = logins.join_next_with_id().await {
+            match joined {
+                Ok((id, res)) => {
+                    results.insert(names[&id].clone(), res);
+                }
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                // the task was cancelled, e.g. because the runtime is shutting down
+                Err(e) => {
+                    results.insert(names[&e.id()].clone(), Err(Error::IoError(e.into())));
+                }
+            }
+        }
+        results
+    }
+}
+
+impl ManagedAccount {
+    #[instrument(name="ManagedAccount::login", skip(self), fields(account = %self.name, provider = %self.provider_name), err)]
+    async fn login(&self) -> crate::error::Result<LoginResponse> {
+        let mut session = self.session.lock().await;
+        if let Some(session) = session.as_ref()
+            && session.logged_in_at.elapsed().is_ok_and(|age| age < self.session_ttl) {
+            debug!("Reusing the account's session");
+            return Ok(session.response.clone());
+        }
+
+        let response = self.provider.authenticate(self.request.clone()).await?;
+        info!("Account logged in");
+        *session = Some(AccountSession {
+            response: response.clone(),
+            logged_in_at: SystemTime::now(),
+        });
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use super::*;
+    use crate::prelude::AuthProvider;
+
+    /// Counts how many logins run at the same time.
+    #[derive(Default)]
+    struct Concurrent {
+        running: AtomicUsize,
+        max: AtomicUsize,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl AuthProvider for Concurrent {
+        async fn authenticate(&self, _req: LoginRequest) -> crate::error::Result<LoginResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(LoginResponse::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_all() {
+        let provider = Arc::new(Concurrent::default());
+        let mut registry = ProviderRegistry::empty();
+        let shared = provider.clone();
+        registry.register("test", move |_: &ProviderOptions| Ok(Box::new(shared.clone()) as BoxedAuthProvider));
+
+        let mut manager = AccountManager::new().with_registry(registry).with_concurrency(2);
+        for name in ["a", "b", "c", "d", "e"] {
+            manager.add_account(name, "test", &ProviderOptions::default(), |req| req).unwrap();
+        }
+        assert_ne!(manager.computer_id("a"), manager.computer_id("b"));
+
+        let results = manager.login_all().await;
+        assert_eq!(results.len(), 5);
+        assert!(results.values().all(Result::is_ok));
+        assert_eq!(provider.max.load(Ordering::SeqCst), 2);
+        assert!(manager.session("c").is_some());
+
+        // the sessions are still valid
+        manager.login_all().await;
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 5);
+        manager.forget_session("c").await;
+        manager.login("c").await.unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 6);
+    }
+}
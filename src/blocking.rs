@@ -0,0 +1,84 @@
+//! Synchronous providers for code without an async runtime, like `reqwest::blocking`.
+//!
+//! Each provider owns a single-threaded tokio runtime and drives the async provider on it, so the
+//! requests, form parsing and every option of the [`LoginRequest`] behave exactly as in the async
+//! API. Calling them from inside an async runtime panics.
+//!
+//! ```no_run
+//! # use ff_auth::prelude::*;
+//! let client = ff_auth::blocking::GlobalClient::new(ff_auth::prelude::GlobalClient::default())?;
+//! let req = LoginRequest::new(HttpClientConfig::default().build()?)
+//!     .with_username("username".to_string())
+//!     .with_password("password");
+//! let response = client.authenticate(req)?;
+//! # Ok::<(), Error>(())
+//! ```
+use std::future::Future;
+use tokio::runtime::{Builder, Runtime};
+use crate::prelude::{AuthProvider, LoginRequest, LoginResponse};
+
+#[cfg(feature = "global")]
+pub type GlobalClient = BlockingProvider<crate::clients::GlobalClient>;
+#[cfg(feature = "steam_shared")]
+pub type SteamClient = BlockingProvider<crate::clients::SteamClient>;
+
+/// Runs an async provider to completion on its own runtime.
+#[derive(Debug)]
+pub struct BlockingProvider<P> {
+    inner: P,
+    runtime: Runtime,
+}
+
+impl<P> BlockingProvider<P> {
+    pub fn new(inner: P) -> crate::error::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(BlockingProvider { inner, runtime })
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Runs one of the async helpers of the provider, e.g. a step-wise login:
+    ///
+    /// ```no_run
+    /// # use ff_auth::prelude::*;
+    /// # fn run(client: ff_auth::blocking::GlobalClient, req: LoginRequest) -> Result<(), Error> {
+    /// let state = client.block_on(client.inner().begin(&req))?;
+    /// let state = client.block_on(state.step(&req))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_on<F>(&self, fut: F) -> F::Output
+    where F: Future {
+        self.runtime.block_on(fut)
+    }
+}
+
+impl<P> BlockingProvider<P>
+where P: AuthProvider {
+    pub fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        self.runtime.block_on(self.inner.authenticate(req))
+    }
+}
+
+#[cfg(all(test, feature = "global"))]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn test_blocking_login() {
+        let transport = MemoryTransport::new();
+        transport.push_page(r#"<form action="login.send" method="post" name="mainForm">
+            <input type="hidden" name="_STORED_" value="token">
+        </form>"#);
+        transport.push_page(include_str!("clients/global_utils/test_content.html"));
+
+        let client = GlobalClient::new(crate::clients::GlobalClient::default()).unwrap();
+        let req = LoginRequest::new(transport)
+            .with_username("user".to_string())
+            .with_password("password");
+        assert_eq!(client.authenticate(req).unwrap().max_expansion, 5);
+    }
+}
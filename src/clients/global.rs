@@ -1,17 +1,43 @@
 use crate::error::Error;
+use crate::events::AuthEvents;
 use crate::prelude::{LoginRequest, LoginResponse};
-use crate::traits::AuthProvider;
+use crate::traits::{AuthProvider, Credentials, TypedAuthProvider};
 use reqwest::{header, RequestBuilder};
 use scraper::{Html, Selector};
 use std::collections::HashMap;
 use std::sync::LazyLock;
 use tracing::{info, instrument};
 use url::Url;
-use crate::clients::global_utils::{get_oauth_login, DefaultHeaders};
+use crate::clients::global_utils::{get_oauth_login, DefaultHeaders, OAuthState};
+
+/// What [`GlobalClient`] does when the server answers that the account has to log in through Steam.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RestartupPolicy {
+    /// Return [`Error::Restartup`] to the caller.
+    #[default]
+    ReturnError,
+    /// Retry the login with a Steam ticket. Returns [`Error::SteamUnavailable`] when the crate was
+    /// built without the `steam` or `steam_dynamic` feature.
+    FallbackToSteam,
+}
 
 #[derive(Debug, Default)]
 pub struct GlobalClient{
+    restartup_policy: RestartupPolicy,
+}
 
+impl GlobalClient {
+    pub fn with_restartup_policy(mut self, policy: RestartupPolicy) -> Self {
+        self.restartup_policy = policy;
+        self
+    }
+
+    /// Fetches the login form for a step-wise login, see [`OAuthState`]. The restartup policy
+    /// does not apply, a Steam-linked account returns [`Error::Restartup`].
+    #[instrument(name="GlobalClient::begin", skip(req), err)]
+    pub async fn begin(&self, req: &LoginRequest) -> crate::error::Result<OAuthState> {
+        OAuthState::fetch(req, None).await
+    }
 }
 
 
@@ -19,6 +45,64 @@ pub struct GlobalClient{
 impl AuthProvider for GlobalClient {
     #[instrument(name="GlobalClient::authenticate", skip(req), ret, err)]
     async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
-        get_oauth_login(req, None).await
+        let events = AuthEvents::new(&req);
+        let res = self.authenticate_with(req, &events).await;
+        events.finish(&res);
+        res
     }
-}
\ No newline at end of file
+}
+
+impl GlobalClient {
+    async fn authenticate_with(&self, req: LoginRequest, events: &AuthEvents) -> crate::error::Result<LoginResponse> {
+        let fallback = (self.restartup_policy == RestartupPolicy::FallbackToSteam).then(|| req.clone());
+
+        match (get_oauth_login(req, None, events).await, fallback) {
+            (Err(Error::Restartup), Some(req)) => {
+                info!("Account requires a Steam login, retrying with a Steam ticket");
+                steam_fallback(req, events).await
+            }
+            (res, _) => res,
+        }
+    }
+}
+
+impl TypedAuthProvider for GlobalClient {
+    type Credentials = Credentials;
+}
+
+#[cfg(feature = "steam_shared")]
+async fn steam_fallback(req: LoginRequest, events: &AuthEvents) -> crate::error::Result<LoginResponse> {
+    crate::clients::SteamClient::default().authenticate_with(req, events).await
+}
+
+#[cfg(not(feature = "steam_shared"))]
+async fn steam_fallback(_req: LoginRequest, _events: &AuthEvents) -> crate::error::Result<LoginResponse> {
+    Err(Error::SteamUnavailable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    fn restartup_request() -> LoginRequest {
+        let transport = MemoryTransport::new();
+        transport.push_page(r#"<script>window.external.user("restartup");</script>"#);
+        LoginRequest::new(transport)
+            .with_username("user".to_string())
+            .with_password("password")
+    }
+
+    #[tokio::test]
+    async fn test_restartup() {
+        let client = GlobalClient::default().with_restartup_policy(RestartupPolicy::ReturnError);
+        assert!(matches!(client.authenticate(restartup_request()).await, Err(Error::Restartup)));
+    }
+
+    #[cfg(not(feature = "steam_shared"))]
+    #[tokio::test]
+    async fn test_restartup_without_steam() {
+        let client = GlobalClient::default().with_restartup_policy(RestartupPolicy::FallbackToSteam);
+        assert!(matches!(client.authenticate(restartup_request()).await, Err(Error::SteamUnavailable)));
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+use url::Url;
+use crate::clients::global_utils::{get_oauth_top, get_oauth_top_url, post_oauth_login, set_field, LaunchParams, LoginForm, Ticket};
+use crate::cookies::CookieJar;
+use crate::error::Error;
+use crate::timeouts::{bounded, LoginStage};
+use crate::prelude::{LoginPath, LoginRequest, LoginResponse, SteamIdentity};
+
+/// Phrases of the login error that asks for a one-time password. The server only reports it as a
+/// localized message, so this is a best-effort match on the languages we request.
+const OTP_ERROR_PHRASES: [&str; 2] = ["one-time password", "ワンタイムパスワード"];
+
+/// A step of the OAuth login. Drive it with [`OAuthState::step`] until it is
+/// [`OAuthState::Completed`]. Every state can be serialized to continue the login later, e.g.
+/// after asking the user for a one-time password.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OAuthState {
+    /// The login form was fetched and can be submitted.
+    FormFetched(OAuthForm),
+    /// The login form was submitted and the answer has not been looked at yet.
+    CredentialsSubmitted(SubmittedForm),
+    /// The server wants a one-time password. Step again with the OTP set on the request.
+    OtpChallenge(OtpChallenge),
+    Completed(LoginResponse),
+}
+
+/// The login form of one login attempt and where it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthForm {
+    pub(crate) form: LoginForm,
+    pub(crate) top_url: Url,
+    pub(crate) login_path: LoginPath,
+    pub(crate) steam_identity: Option<SteamIdentity>,
+    /// The cookies of the login so far.
+    #[serde(default)]
+    pub(crate) cookies: CookieJar,
+}
+
+/// A submitted login form and the page the server answered with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmittedForm {
+    pub(crate) form: OAuthForm,
+    pub(crate) response: String,
+    pub(crate) otp_sent: bool,
+    /// The cookies after the submit.
+    #[serde(default)]
+    pub(crate) cookies: CookieJar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OtpChallenge {
+    pub(crate) form: OAuthForm,
+    pub(crate) message: String,
+    pub(crate) rejected: bool,
+}
+
+impl OAuthForm {
+    pub fn form(&self) -> &LoginForm {
+        &self.form
+    }
+    pub fn login_path(&self) -> LoginPath {
+        self.login_path
+    }
+
+    /// Fills in the credentials and OTP of `req` and posts the form.
+    #[instrument(name="OAuthForm::submit", skip_all, err)]
+    pub(crate) async fn submit(&self, req: &LoginRequest) -> crate::error::Result<SubmittedForm> {
+        let mut input = self.form.fields.clone();
+
+        match self.login_path {
+            LoginPath::Global => {
+                let Some(username) = req.account_name() else {
+                    return Err(Error::MissingUsername);
+                };
+                let Some(password) = req.resolve_password().await? else {
+                    return Err(Error::MissingPassword);
+                };
+                set_field(&mut input, "sqexid", username);
+                set_field(&mut input, "password", password.into_unsecure());
+            }
+            LoginPath::Steam => {
+                // for steam requests we do not use the username or password
+                if req.username.is_some() || req.password.is_some() || req.credential_provider.is_some() {
+                    warn!("Steam logins use the linked Square Enix ID, ignoring the username and password");
+                }
+            }
+        }
+        if let Some(otp) = &req.otp {
+            set_field(&mut input, "otppw", otp.clone());
+        }
+
+        let login_url = self.top_url.join(&self.form.action)?;
+        info!("Performing OAuth login");
+        let mut cookies = self.cookies.clone();
+        let response = post_oauth_login(req, login_url, &self.top_url, &input, &mut cookies).await?;
+
+        Ok(SubmittedForm {
+            form: self.clone(),
+            response,
+            otp_sent: req.otp.is_some(),
+            cookies,
+        })
+    }
+}
+
+impl SubmittedForm {
+    /// Reads the result out of the page the server answered with.
+    pub(crate) fn resolve(&self) -> crate::error::Result<OAuthState> {
+        let Some(params) = LaunchParams::from_html(&self.response) else {
+            return Err(Error::LoginFailure);
+        };
+        if params.require("auth")? != "ok" {
+            let message = params.get("err").unwrap_or_default().to_string();
+            let lowercase = message.to_lowercase();
+            if OTP_ERROR_PHRASES.iter().any(|phrase| lowercase.contains(phrase)) {
+                return Ok(OAuthState::OtpChallenge(OtpChallenge {
+                    form: OAuthForm { cookies: self.cookies.clone(), ..self.form.clone() },
+                    message,
+                    rejected: self.otp_sent,
+                }));
+            }
+            return Err(Error::LoginFailureMessage(message));
+        }
+
+        Ok(OAuthState::Completed(LoginResponse{
+            session_id: params.require("sid")?.to_string(),
+            region: params.parse_value("region")?,
+            terms_accepted: params.get("terms") != Some("0"),
+            playable: params.get("playable") != Some("0"),
+            max_expansion: params.parse_value("maxex")?,
+            login_path: self.form.login_path,
+            steam_identity: self.form.steam_identity.clone(),
+            cookies: self.cookies.clone(),
+        }))
+    }
+}
+
+impl OtpChallenge {
+    /// The message the server showed.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    /// Whether a one-time password was sent and the server turned it down.
+    pub fn rejected(&self) -> bool {
+        self.rejected
+    }
+
+    /// The error to give up with, after the server rejected `rejected` OTP submits.
+    pub(crate) fn into_error(self, rejected: u32) -> Error {
+        if self.rejected {
+            Error::InvalidOtp { message: self.message, rejected }
+        } else {
+            Error::OtpRequired
+        }
+    }
+}
+
+impl OAuthState {
+    /// Fetches the login form, the first step of every login.
+    #[instrument(name="OAuthState::fetch", skip_all, err)]
+    pub(crate) async fn fetch(req: &LoginRequest, steam: Option<(Ticket, SteamIdentity)>) -> crate::error::Result<OAuthState> {
+        let login_path = if steam.is_some() { LoginPath::Steam } else { LoginPath::Global };
+        let (ticket, steam_identity) = steam.unzip();
+        let top_url = get_oauth_top_url(req, ticket)?;
+        let mut cookies = CookieJar::for_login(&top_url);
+
+        let form = bounded(req, LoginStage::LoginPage, get_oauth_top(req, top_url.clone(), login_path, &mut cookies)).await?;
+        if form.method != "post" {
+            return Err(Error::ProtocolMismatch(format!("the login form is sent with {}", form.method.to_uppercase())));
+        }
+
+        Ok(OAuthState::FormFetched(OAuthForm { form, top_url, login_path, steam_identity, cookies }))
+    }
+
+    /// Advances the login by one step. The state is left as it was, so a failed submit can be
+    /// retried with a corrected request without fetching the form again. The timeouts and
+    /// cancellation token of `req` apply to the step.
+    ///
+    /// Submitting (from [`OAuthState::FormFetched`] or [`OAuthState::OtpChallenge`]) sends the
+    /// credentials and OTP of `req`. Resolving [`OAuthState::CredentialsSubmitted`] does not
+    /// send anything and returns the login errors, and [`OAuthState::Completed`] stays as is.
+    pub async fn step(&self, req: &LoginRequest) -> crate::error::Result<OAuthState> {
+        match self {
+            OAuthState::FormFetched(form) => {
+                Ok(OAuthState::CredentialsSubmitted(bounded(req, LoginStage::Submit, form.submit(req)).await?))
+            }
+            OAuthState::OtpChallenge(challenge) => {
+                Ok(OAuthState::CredentialsSubmitted(bounded(req, LoginStage::Submit, challenge.form.submit(req)).await?))
+            }
+            OAuthState::CredentialsSubmitted(submitted) => submitted.resolve(),
+            OAuthState::Completed(_) => Ok(self.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::OAuthProtocol;
+
+    fn submitted(response: &str, otp_sent: bool) -> SubmittedForm {
+        let form = LoginForm::parse(r#"<form action="login.send" method="post" name="mainForm">
+            <input type="hidden" name="_STORED_" value="token">
+        </form>"#, &OAuthProtocol::default()).unwrap();
+        SubmittedForm {
+            form: OAuthForm {
+                form,
+                top_url: Url::parse("https://ffxiv-login.square-enix.com/oauth/ffxivarr/login/top").unwrap(),
+                login_path: LoginPath::Global,
+                steam_identity: None,
+                cookies: CookieJar::default(),
+            },
+            response: response.to_string(),
+            otp_sent,
+            cookies: CookieJar::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let state = submitted(include_str!("test_content.html"), false).resolve().unwrap();
+        let OAuthState::Completed(res) = state else {
+            panic!("expected a completed login, got {:?}", state);
+        };
+        assert_eq!(res.max_expansion, 5);
+
+        let otp = r#"window.external.user("login=auth,ng,err,Please enter your one-time password.");"#;
+        let state = submitted(otp, true).resolve().unwrap();
+        let OAuthState::OtpChallenge(challenge) = state else {
+            panic!("expected an OTP challenge, got {:?}", state);
+        };
+        assert!(challenge.rejected());
+    }
+}
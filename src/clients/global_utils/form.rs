@@ -0,0 +1,331 @@
+use std::fmt::{Display, Formatter};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use crate::error::Error;
+use crate::protocol::OAuthProtocol;
+
+/// The token field of the login form.
+const STORED_FIELD: &str = "_STORED_";
+
+/// How much of the page text a [`PageSummary`] keeps.
+const SUMMARY_TEXT_LEN: usize = 160;
+
+/// The `mainForm` login form served by `login/top`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginForm {
+    pub(crate) action: String,
+    pub(crate) method: String,
+    /// The form data set in document order; a name can occur more than once.
+    pub(crate) fields: Vec<(String, String)>,
+    pub(crate) linked_account: Option<String>,
+}
+
+/// What a page that is not the expected login page looks like, for error messages and logs. It
+/// never contains field values or script contents, and personal-looking words of the text are
+/// masked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageSummary {
+    pub title: Option<String>,
+    pub heading: Option<String>,
+    /// The `name` or `id` of every form on the page.
+    pub forms: Vec<String>,
+    /// The start of the visible text.
+    pub text: String,
+}
+
+impl LoginForm {
+    /// The Square Enix ID the Steam account is linked to. The server pre-fills it as a read-only
+    /// field on Steam logins, so it is `None` for regular logins.
+    pub fn linked_account(&self) -> Option<&str> {
+        self.linked_account.as_deref()
+    }
+
+    /// The name-value pairs the form submits, in document order.
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// Finds the login form with the selectors of `protocol`, falling back to the form that
+    /// carries the `_STORED_` token, and reads the values it would submit.
+    pub(crate) fn parse(text: &str, protocol: &OAuthProtocol) -> crate::error::Result<LoginForm> {
+        let document = Html::parse_document(text);
+        let Some(form) = find_form(&document, &protocol.form_selectors) else {
+            return Err(Error::UnexpectedPage(PageSummary::of(&document)));
+        };
+
+        let action = form.value().attr("action").unwrap_or_default().to_string();
+        let method = form.value().attr("method").unwrap_or("get").to_ascii_lowercase();
+        let fields = form_data(form);
+
+        // the linked Square Enix ID is the only sqexid the user cannot edit
+        let linked_account = form.select(&Selector::parse("input[name=sqexid]").unwrap())
+            .map(|input| input.value())
+            .find(|input| input.attr("type") == Some("hidden") || input.attr("readonly").is_some())
+            .and_then(|input| input.attr("value"))
+            .filter(|value| !value.is_empty())
+            .map(str::to_string);
+
+        // every version of the login form carries the _STORED_ token the submit is checked against
+        if !fields.iter().any(|(name, _)| name == STORED_FIELD) {
+            return Err(Error::ProtocolMismatch(format!("the login form has no {STORED_FIELD} field")));
+        }
+
+        Ok(LoginForm { action, method, fields, linked_account })
+    }
+}
+
+fn find_form<'a>(document: &'a Html, selectors: &[String]) -> Option<ElementRef<'a>> {
+    for selector in selectors {
+        match Selector::parse(selector) {
+            Ok(parsed) => {
+                if let Some(form) = document.select(&parsed).find(|e| e.value().name() == "form") {
+                    debug!(%selector, "Found the login form");
+                    return Some(form);
+                }
+            }
+            Err(e) => warn!(%selector, "Ignoring invalid form selector: {e}"),
+        }
+    }
+
+    let stored = Selector::parse(&format!("input[name={STORED_FIELD}]")).unwrap();
+    document.select(&Selector::parse("form").unwrap())
+        .find(|form| form.select(&stored).next().is_some())
+}
+
+/// The name-value pairs submitting `form` sends, following the HTML form submission rules:
+/// disabled controls and buttons are left out, unchecked checkboxes and radio buttons too, a
+/// checked one without a value sends `on`, and a `<select>` sends its selected options, or its
+/// first option when none is selected. Pairs are kept in document order, repeated names and every
+/// option of a multiple select included.
+fn form_data(form: ElementRef) -> Vec<(String, String)> {
+    let controls = Selector::parse("input, select, textarea").unwrap();
+    let mut fields = Vec::new();
+
+    for control in form.select(&controls) {
+        let element = control.value();
+        let Some(name) = element.attr("name").filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        if is_disabled(control) {
+            continue;
+        }
+
+        match element.name() {
+            "input" => {
+                let kind = element.attr("type").unwrap_or("text").to_ascii_lowercase();
+                match kind.as_str() {
+                    "submit" | "button" | "reset" | "image" | "file" => {}
+                    "checkbox" | "radio" => {
+                        if element.attr("checked").is_some() {
+                            fields.push((name.to_string(), element.attr("value").unwrap_or("on").to_string()));
+                        }
+                    }
+                    _ => {
+                        fields.push((name.to_string(), element.attr("value").unwrap_or_default().to_string()));
+                    }
+                }
+            }
+            "select" => {
+                let options: Vec<_> = control.select(&Selector::parse("option").unwrap())
+                    .filter(|option| option.value().attr("disabled").is_none())
+                    .collect();
+                let selected: Vec<_> = options.iter().filter(|option| option.value().attr("selected").is_some()).collect();
+                let chosen = match (selected.is_empty(), element.attr("multiple").is_some()) {
+                    (false, _) => selected,
+                    (true, false) => options.iter().take(1).collect(),
+                    (true, true) => Vec::new(),
+                };
+                for option in chosen {
+                    let value = match option.value().attr("value") {
+                        Some(value) => value.to_string(),
+                        None => collapse_whitespace(&option.text().collect::<String>()),
+                    };
+                    fields.push((name.to_string(), value));
+                }
+            }
+            "textarea" => {
+                fields.push((name.to_string(), control.text().collect()));
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+/// Replaces the values of `name` with `value`, keeping the position of its first pair, or adds it.
+pub(crate) fn set_field(fields: &mut Vec<(String, String)>, name: &str, value: String) {
+    match fields.iter().position(|(n, _)| n == name) {
+        Some(first) => {
+            fields[first].1 = value;
+            let mut i = 0;
+            fields.retain(|(n, _)| {
+                i += 1;
+                i - 1 == first || n != name
+            });
+        }
+        None => fields.push((name.to_string(), value)),
+    }
+}
+
+/// Whether the control or a `<fieldset>` around it is disabled.
+fn is_disabled(control: ElementRef) -> bool {
+    control.value().attr("disabled").is_some()
+        || control.ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|e| e.value().name() == "fieldset" && e.value().attr("disabled").is_some())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl PageSummary {
+    pub(crate) fn of(document: &Html) -> Self {
+        let first_text = |selector: &str| document.select(&Selector::parse(selector).unwrap())
+            .next()
+            .map(|e| sanitize(&e.text().collect::<String>()))
+            .filter(|text| !text.is_empty());
+        let forms = document.select(&Selector::parse("form").unwrap())
+            .map(|form| form.value().attr("name").or(form.value().id()).unwrap_or("unnamed").to_string())
+            .collect();
+
+        let mut text = String::new();
+        if let Some(body) = document.select(&Selector::parse("body").unwrap()).next() {
+            for node in body.descendants() {
+                let Some(part) = node.value().as_text() else {
+                    continue;
+                };
+                let hidden = node.ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .any(|e| matches!(e.value().name(), "script" | "style" | "noscript" | "template"));
+                if !hidden {
+                    text.push(' ');
+                    text.push_str(part);
+                }
+            }
+        }
+        let mut text = sanitize(&text);
+        if let Some((end, _)) = text.char_indices().nth(SUMMARY_TEXT_LEN) {
+            text.truncate(end);
+            text.push('…');
+        }
+
+        PageSummary {
+            title: first_text("title"),
+            heading: first_text("h1, h2"),
+            forms,
+            text,
+        }
+    }
+}
+
+/// Collapses whitespace and masks e-mail addresses and long tokens that may identify someone.
+fn sanitize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            if word.contains('@') {
+                "[email]"
+            } else if word.len() >= 16 && word.chars().any(|c| c.is_ascii_digit()) {
+                "[redacted]"
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Display for PageSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "title {:?}", self.title.as_deref().unwrap_or_default())?;
+        if let Some(heading) = &self.heading {
+            write!(f, ", heading {heading:?}")?;
+        }
+        write!(f, ", forms [{}], text {:?}", self.forms.join(", "), self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> crate::error::Result<LoginForm> {
+        LoginForm::parse(text, &OAuthProtocol::default())
+    }
+
+    #[test]
+    fn test_linked_account() {
+        let steam = r#"<form action="login.send" method="post" name="mainForm">
+            <input type="hidden" name="_STORED_" value="token">
+            <input name="sqexid" type="hidden" value="linked_user"/>
+            <input type="password" name="password">
+        </form>"#;
+        let form = parse(steam).unwrap();
+        assert_eq!(form.linked_account(), Some("linked_user"));
+
+        let global = r#"<form action="login.send" method="post" name="mainForm">
+            <input type="hidden" name="_STORED_" value="token">
+            <input type="text" name="sqexid" value="">
+        </form>"#;
+        let form = parse(global).unwrap();
+        assert_eq!(form.linked_account(), None);
+    }
+
+    #[test]
+    fn test_protocol_mismatch() {
+        let no_token = r#"<form action="login.send" method="post" name="mainForm">
+            <input type="text" name="sqexid" value="">
+        </form>"#;
+        assert!(matches!(parse(no_token), Err(Error::ProtocolMismatch(_))));
+    }
+
+    #[test]
+    fn test_form_data() {
+        let page = r#"<form action="login.send" method="POST" name="loginForm">
+            <input type="hidden" name="_STORED_" value="token">
+            <input type="text" name="sqexid">
+            <input type="checkbox" name="remember" checked>
+            <input type="checkbox" name="unchecked" value="1">
+            <input type="radio" name="mode" value="a">
+            <input type="radio" name="mode" value="b" checked>
+            <input type="text" name="off" value="x" disabled>
+            <fieldset disabled><input type="text" name="inside" value="x"></fieldset>
+            <select name="lang"><option value="ja">日本語</option><option selected> English </option></select>
+            <select name="first"><option value="1">1</option><option value="2">2</option></select>
+            <select name="tags" multiple><option selected>a</option><option>b</option><option selected>c</option></select>
+            <input type="hidden" name="tags" value="d">
+            <textarea name="note">hi</textarea>
+            <input type="submit" name="go" value="Log in">
+        </form>"#;
+        let form = parse(page).unwrap();
+        assert_eq!(form.method, "post");
+
+        let expected: Vec<(String, String)> = [
+            ("_STORED_", "token"), ("sqexid", ""), ("remember", "on"), ("mode", "b"),
+            ("lang", "English"), ("first", "1"), ("tags", "a"), ("tags", "c"), ("tags", "d"), ("note", "hi"),
+        ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(form.fields, expected);
+
+        let mut fields = form.fields.clone();
+        set_field(&mut fields, "tags", "x".to_string());
+        set_field(&mut fields, "otppw", "123456".to_string());
+        assert_eq!(fields[6], ("tags".to_string(), "x".to_string()));
+        assert_eq!(fields.iter().filter(|(name, _)| name == "tags").count(), 1);
+        assert_eq!(fields.last().unwrap().0, "otppw");
+    }
+
+    #[test]
+    fn test_unexpected_page() {
+        let page = r#"<html><head><title>Maintenance</title><script>var sid = "secret";</script></head>
+            <body><h1>Under maintenance</h1><p>Contact someone@example.com, code 0123456789abcdef0123.</p>
+            <form name="search"><input name="q" value="private"></form></body></html>"#;
+        let Err(Error::UnexpectedPage(summary)) = parse(page) else {
+            panic!("expected an unexpected page error");
+        };
+        assert_eq!(summary.title.as_deref(), Some("Maintenance"));
+        assert_eq!(summary.forms, vec!["search"]);
+        assert_eq!(summary.text, "Under maintenance Contact [email] code [redacted]");
+        assert!(!summary.to_string().contains("secret") && !summary.to_string().contains("private"));
+    }
+}
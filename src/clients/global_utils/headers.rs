@@ -1,29 +1,39 @@
 use std::sync::LazyLock;
-use reqwest::{header, RequestBuilder};
 use sha1::{Digest, Sha1};
+use crate::identity::ComputerId;
+use crate::prelude::LoginRequest;
+use crate::transport::HttpRequest;
 
-static USERAGENT: LazyLock<String> = LazyLock::new(|| {
-    format!("SQEXAuthor/2.0.0(Windows 6.2; ja-jp; {})", make_computer_id())
-});
+/// The computer ID of requests that do not set one, derived from this machine.
+static COMPUTER_ID: LazyLock<ComputerId> = LazyLock::new(ComputerId::for_machine);
 
 
 pub(crate) trait DefaultHeaders {
-    fn default_ffxiv_headers(self) -> Self;
+    fn default_ffxiv_headers(self, req: &LoginRequest) -> Self;
 }
 
-impl DefaultHeaders for RequestBuilder {
-    fn default_ffxiv_headers(self) -> Self {
-        self.header(header::ACCEPT, "image/gif, image/jpeg, image/pjpeg, application/x-ms-application, application/xaml+xml, application/x-ms-xbap, */*")
-            .header(header::ACCEPT_ENCODING, "gzip, deflate")
-            .header(header::ACCEPT_LANGUAGE, "en-US")
-            .header(header::USER_AGENT, &*USERAGENT)
-            .header(header::CONNECTION, "Keep-Alive")
-            .header(header::UPGRADE_INSECURE_REQUESTS, "true")
+impl DefaultHeaders for HttpRequest {
+    fn default_ffxiv_headers(self, req: &LoginRequest) -> Self {
+        let computer_id = req.computer_id.as_ref().unwrap_or(&COMPUTER_ID);
+        req.fingerprint.headers(computer_id).into_iter()
+            .fold(self, |request, (name, value)| request.header(name, value))
     }
 }
 
+impl ComputerId {
+    /// The ID the launcher derives from this machine: its hostname, user, OS and CPU count.
+    pub fn for_machine() -> Self {
+        make_computer_id("")
+    }
+    /// An ID derived from this machine and `profile`, different for every profile and stable
+    /// across runs on the same machine.
+    pub fn for_profile(profile: &str) -> Self {
+        make_computer_id(profile)
+    }
+}
 
-fn make_computer_id() -> String {
+/// Hashes this machine and `seed` into a computer ID.
+fn make_computer_id(seed: &str) -> ComputerId {
     // Get system information (you'll need appropriate crates for cross-platform support)
     let machine_name = hostname::get()
         .unwrap_or_default()
@@ -39,8 +49,8 @@ fn make_computer_id() -> String {
     let processor_count = num_cpus::get().to_string();
 
     // Create hash string by concatenating system info
-    let hash_string = format!("{}{}{}{}",
-                              machine_name, user_name.unwrap_or("username".to_string()), os_version, processor_count);
+    let hash_string = format!("{}{}{}{}{}",
+                              machine_name, user_name.unwrap_or("username".to_string()), os_version, processor_count, seed);
 
     // Convert to UTF-16 bytes (equivalent to C#'s Encoding.Unicode)
     let utf16_bytes: Vec<u8> = hash_string
@@ -53,19 +63,6 @@ fn make_computer_id() -> String {
     hasher.update(&utf16_bytes);
     let hash_result = hasher.finalize();
 
-    // Create 5-byte array
-    let mut bytes = [0u8; 5];
-
-    // Copy first 4 bytes of hash to positions 1-4
-    bytes[1..5].copy_from_slice(&hash_result[0..4]);
-
-    // Calculate checksum (negative sum of bytes 1-4) with wrapping arithmetic
-    let sum = bytes[1]
-        .wrapping_add(bytes[2])
-        .wrapping_add(bytes[3])
-        .wrapping_add(bytes[4]);
-    bytes[0] = sum.wrapping_neg();
-
-    // Convert to lowercase hex string
-    hex::encode(bytes)
-}
\ No newline at end of file
+    // the first 4 bytes of the hash, preceded by the checksum byte
+    ComputerId::from_machine_bytes([hash_result[0], hash_result[1], hash_result[2], hash_result[3]])
+}
@@ -0,0 +1,237 @@
+use std::str::FromStr;
+use scraper::{Html, Selector};
+use tracing::info;
+use crate::error::Error;
+
+/// A JavaScript token, as far as finding `window.external.user("...")` calls needs it.
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Regex,
+    Punct(char),
+}
+
+/// Keywords after which a `/` starts a regular expression instead of dividing.
+const REGEX_KEYWORDS: &[&str] = &["return", "typeof", "instanceof", "in", "of", "new", "delete", "void", "throw", "case", "do", "else", "yield", "await"];
+
+/// Whether a `/` after `previous` starts a regular expression literal: it does where an
+/// expression starts, and divides after a value.
+fn starts_regex(previous: Option<&Token>) -> bool {
+    match previous {
+        None | Some(Token::Regex) => true,
+        Some(Token::Ident(ident)) => REGEX_KEYWORDS.contains(&ident.as_str()),
+        Some(Token::Str(_)) => false,
+        Some(Token::Punct(c)) => !matches!(c, ')' | ']'),
+    }
+}
+
+/// Splits JavaScript into identifiers, string and regular expression literals and punctuation,
+/// skipping whitespace and comments. Unlike a plain text search it does not mistake `//` or `/*`
+/// inside a string for a comment, nor a call inside a comment for a real one, and quotes inside
+/// a regular expression do not start a string.
+fn tokenize(script: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = script.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = '\0';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '/' if starts_regex(tokens.last()) => {
+                regex_literal(&mut chars);
+                tokens.push(Token::Regex);
+            }
+            '"' | '\'' | '`' => tokens.push(Token::Str(string_literal(c, &mut chars))),
+            c if c.is_alphanumeric() || c == '_' || c == '$' => {
+                let mut ident = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_' || c == '$') {
+                    ident.push(c);
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+/// Skips a regular expression literal up to its closing `/`, which does not count inside a
+/// `[...]` class. A literal cannot span lines, so an unclosed one ends at the line break.
+fn regex_literal(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    let mut in_class = false;
+    while let Some(c) = chars.next_if(|&c| c != '\n') {
+        match c {
+            '\\' => {
+                chars.next_if(|&c| c != '\n');
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => break,
+            _ => {}
+        }
+    }
+}
+
+/// Reads a string literal up to the closing `quote`, resolving its escapes.
+fn string_literal(quote: char, chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == quote => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('r') => value.push('\r'),
+                Some('t') => value.push('\t'),
+                Some('b') => value.push('\u{8}'),
+                Some('f') => value.push('\u{c}'),
+                Some('v') => value.push('\u{b}'),
+                Some('0') => value.push('\0'),
+                Some('x') => push_code_point(&mut value, chars.by_ref().take(2).collect()),
+                Some('u') if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    push_code_point(&mut value, chars.by_ref().take_while(|&c| c != '}').collect());
+                }
+                Some('u') => push_code_point(&mut value, chars.by_ref().take(4).collect()),
+                // a line continuation
+                Some('\n') => {}
+                Some(c) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    value
+}
+
+fn push_code_point(value: &mut String, hex: String) {
+    match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+        Some(c) => value.push(c),
+        None => value.push(char::REPLACEMENT_CHARACTER),
+    }
+}
+
+/// The string arguments of every `window.external.user(...)` call in the scripts of a page, in
+/// order. A response without `<script>` elements is read as a script itself.
+pub(crate) fn external_user_calls(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let scripts: Vec<String> = document.select(&Selector::parse("script").unwrap())
+        .map(|script| script.text().collect())
+        .collect();
+    let scripts = if scripts.is_empty() { vec![html.to_string()] } else { scripts };
+
+    let mut calls = Vec::new();
+    for script in scripts {
+        let tokens = tokenize(&script);
+        for window in tokens.windows(7) {
+            if let [Token::Ident(window), Token::Punct('.'), Token::Ident(external), Token::Punct('.'), Token::Ident(user), Token::Punct('('), Token::Str(argument)] = window
+                && window == "window" && external == "external" && user == "user" {
+                calls.push(argument.clone());
+            }
+        }
+    }
+    calls
+}
+
+/// The keys the login server sends, other than `err`.
+const LAUNCH_KEYS: &[&str] = &["auth", "sid", "terms", "region", "etmadd", "playable", "ps3pkg", "maxex", "product"];
+
+/// The `login=key,value,key,value` parameters the login page hands to the launcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LaunchParams {
+    params: Vec<(String, String)>,
+}
+
+impl LaunchParams {
+    /// The parameters of the first `window.external.user("login=...")` call of a page.
+    pub(crate) fn from_html(html: &str) -> Option<Self> {
+        let params = external_user_calls(html).into_iter()
+            .find_map(|call| call.strip_prefix("login=").map(LaunchParams::parse))?;
+        info!(keys = ?params.params.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), "Extracted launch params");
+        Some(params)
+    }
+
+    /// Parses `key,value,key,value`. The `err` message may contain commas itself, so it runs up
+    /// to the next known key.
+    fn parse(login: &str) -> Self {
+        let parts: Vec<&str> = login.split(',').collect();
+        let mut params = Vec::new();
+        let mut i = 0;
+        while i + 1 < parts.len() {
+            let key = parts[i];
+            let end = if key == "err" {
+                (i + 2..parts.len()).find(|&j| LAUNCH_KEYS.contains(&parts[j])).unwrap_or(parts.len())
+            } else {
+                i + 2
+            };
+            params.push((key.to_string(), parts[i + 1..end].join(",")));
+            i = end;
+        }
+        LaunchParams { params }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.params.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn require(&self, key: &'static str) -> crate::error::Result<&str> {
+        self.get(key).ok_or(Error::MissingLaunchParam(key))
+    }
+
+    pub(crate) fn parse_value<T: FromStr>(&self, key: &'static str) -> crate::error::Result<T> {
+        let value = self.require(key)?;
+        value.parse().map_err(|_| Error::InvalidLaunchParam { key, value: value.to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_user_calls() {
+        let script = r#"<script>
+            var url = "https://example.com/*";
+            // window.external.user("login=auth,ng,err,commented out");
+            /* window.external.user("restartup"); */
+            window.external.user('login=auth,ng,err,Can\'t log in, try \"again\".!');
+        </script>"#;
+        assert_eq!(external_user_calls(script), vec!["login=auth,ng,err,Can't log in, try \"again\".!"]);
+
+        let params = LaunchParams::from_html(script).unwrap();
+        assert_eq!(params.get("auth"), Some("ng"));
+        assert_eq!(params.get("err"), Some("Can't log in, try \"again\".!"));
+
+        let regex = r#"<script>
+            var quote = /"/g, half = width / 2, escaped = text.replace(/['\/]/g, "");
+            if (/\d+/.test(version)) { window.external.user("login=auth,ok,sid,abc,region,3,maxex,5"); }
+        </script>"#;
+        assert_eq!(external_user_calls(regex), vec!["login=auth,ok,sid,abc,region,3,maxex,5"]);
+    }
+
+    #[test]
+    fn test_launch_params() {
+        let params = LaunchParams::from_html(include_str!("test_content.html")).unwrap();
+        assert_eq!(params.require("sid").unwrap(), "REDACTED_UUID");
+        assert_eq!(params.parse_value::<u8>("maxex").unwrap(), 5);
+
+        let params = LaunchParams::parse("auth,ng,err,Wrong ID, or password.,region,3");
+        assert_eq!(params.get("err"), Some("Wrong ID, or password."));
+        assert_eq!(params.get("region"), Some("3"));
+
+        let params = LaunchParams::parse("auth,ok,region,x");
+        assert!(matches!(params.require("sid"), Err(Error::MissingLaunchParam("sid"))));
+        assert!(matches!(params.parse_value::<u8>("region"), Err(Error::InvalidLaunchParam { key: "region", .. })));
+    }
+}
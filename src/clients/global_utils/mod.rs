@@ -1,25 +1,38 @@
-use std::collections::HashMap;
 use std::sync::LazyLock;
-use reqwest::{header, RequestBuilder};
-use scraper::{Html, Selector};
+use reqwest::header::{self, HeaderValue};
 use tracing::{debug, info, instrument, trace};
 use url::Url;
-use crate::prelude::{LoginRequest, LoginResponse};
-#[cfg(feature = "steam")]
+use crate::cookies::CookieJar;
+use crate::prelude::{LoginPath, LoginRequest, LoginResponse, SteamIdentity};
+#[cfg(feature = "steam_shared")]
 mod crt_rand;
-#[cfg(feature = "steam")]
+#[cfg(feature = "steam_shared")]
 pub(crate) use crt_rand::*;
 
+mod flow;
+mod form;
 mod headers;
+mod launch_params;
 mod ticket;
 
+pub use flow::*;
+pub use form::*;
 pub(crate) use ticket::*;
 pub(crate) use headers::*;
+pub(crate) use launch_params::*;
 use crate::error::Error;
+use crate::events::{AuthEvents, AuthStage};
+use crate::otp::OtpContext;
+use crate::retry::send_with_retry;
+use crate::timeouts::{bounded, LoginStage};
+use crate::transport::HttpRequest;
 
+/// How often an OTP provider is asked during one login before the login fails.
+const MAX_OTP_ATTEMPTS: u32 = 3;
 
 
-fn get_oauth_top_url(req: &LoginRequest, steam: Option<Ticket>) -> crate::error::Result<Url> {
+
+pub(crate) fn get_oauth_top_url(req: &LoginRequest, steam: Option<Ticket>) -> crate::error::Result<Url> {
     let mut params = Vec::new();
 
     params.push(("lng", "en".to_string()));//TODO: enable customization for the lng
@@ -33,12 +46,20 @@ fn get_oauth_top_url(req: &LoginRequest, steam: Option<Ticket>) -> crate::error:
         "0".to_string()
     }));
 
-    params.push(("cssmode", "1".to_string()));
-    params.push(("isnew", "1".to_string()));
-    params.push(("launchver", "3".to_string()));
+    params.push(("cssmode", req.protocol.cssmode.to_string()));
+    params.push(("isnew", req.protocol.isnew.to_string()));
+    params.push(("launchver", req.protocol.launchver.to_string()));
+    for (key, value) in &req.protocol.extra_params {
+        params.push((key, value.clone()));
+    }
+    for (key, value) in req.fingerprint.query_params() {
+        params.push((key, value.to_string()));
+    }
 
     if let Some(steam) = steam {
-        params.push(("issteam", "1".to_string()));
+        if !params.iter().any(|(key, _)| *key == "issteam") {
+            params.push(("issteam", "1".to_string()));
+        }
         params.push(("session_ticket", steam.text));
         params.push(("ticket_size", steam.length.to_string()));
     }
@@ -47,184 +68,317 @@ fn get_oauth_top_url(req: &LoginRequest, steam: Option<Ticket>) -> crate::error:
 }
 
 
-pub(crate) async fn get_oauth_login(mut req: LoginRequest, steam: Option<Ticket>) -> crate::error::Result<LoginResponse> {
+pub(crate) async fn get_oauth_login(req: LoginRequest, steam: Option<(Ticket, SteamIdentity)>, events: &AuthEvents) -> crate::error::Result<LoginResponse> {
     if steam.is_none() {
-        if req.username.is_none() {
+        if req.account_name().is_none() {
             return Err(Error::MissingUsername);
         }
-        if req.password.is_none() {
+        if req.password.is_none() && req.credential_provider.is_none() {
             return Err(Error::MissingPassword);
         }
-    } else {
-        // for steam requests we do not use the username or password
-        req.username.take();
-        req.password.take();
-    }
-
-    let url = get_oauth_top_url(&req, steam)?;
-
-    let (action, method, mut input) = get_oauth_top(req.client.clone(), url.clone()).await?;
-    if method != "post".to_string() ||  input.is_empty() {
-        return Err(Error::MissingLoginForm);
     }
 
-    let login_url = url.join(&action)?;
-
-    info!("Performing OAuth login");
-    let builder = req.client.post(login_url)
-        .default_ffxiv_headers()
-        .header(header::REFERER, url.as_str())
-        .header(header::COOKIE, "_rsid=\"\"");
-
-    if let Some(username) = req.username {
-        input.insert("sqexid".to_string(), username);
-    }
-    if let Some(password) = req.password {
-        input.insert("password".to_string(), password.into_unsecure());
-    }
-    if let Some(otp) = req.otp {
-        input.insert("otppw".to_string(), otp);
+    let mut req = req;
+    let mut otp_attempt = 0;
+    let mut rejected_otps = 0;
+    events.emit(AuthStage::FetchingLoginPage);
+    let mut state = OAuthState::fetch(&req, steam).await?;
+    loop {
+        state = match state {
+            OAuthState::Completed(res) => {
+                events.emit(AuthStage::SessionRegistered);
+                return Ok(res)
+            }
+            OAuthState::FormFetched(_) => {
+                match req.otp {
+                    Some(_) => otp_attempt += 1,
+                    None => req.otp = next_otp(&req, &mut otp_attempt, None, events).await?,
+                }
+                events.emit(AuthStage::SubmittingCredentials);
+                state.step(&req).await?
+            }
+            OAuthState::OtpChallenge(challenge) => {
+                if challenge.rejected() {
+                    rejected_otps += 1;
+                }
+                match next_otp(&req, &mut otp_attempt, Some(challenge.message()), events).await? {
+                    Some(otp) => {
+                        req.otp = Some(otp);
+                        events.emit(AuthStage::SubmittingCredentials);
+                        OAuthState::OtpChallenge(challenge).step(&req).await?
+                    }
+                    None => return Err(challenge.into_error(rejected_otps)),
+                }
+            }
+            state => state.step(&req).await?,
+        };
     }
-    
-    let res = builder.form(&input).send().await?.text().await?;
+}
 
-    let Some(params) = extract_launch_params(&res) else {
-        return Err(Error::LoginFailure);
+/// Asks the OTP provider of `req` for the next one-time password, giving up after [`MAX_OTP_ATTEMPTS`].
+async fn next_otp(req: &LoginRequest, attempt: &mut u32, message: Option<&str>, events: &AuthEvents) -> crate::error::Result<Option<String>> {
+    let Some(provider) = &req.otp_provider else {
+        return Ok(None);
     };
-    if params["auth"] != "ok" {
-        return Err(Error::LoginFailureMessage(params["err"].to_string()));
+    if *attempt >= MAX_OTP_ATTEMPTS {
+        return Ok(None);
     }
+    *attempt += 1;
 
-    Ok(LoginResponse{
-        session_id: params["sid"].to_string(),
-        region: params["region"].parse()?,
-        terms_accepted: params["terms"] != "0",
-        playable: params["playable"] != "0",
-        max_expansion: params["maxex"].parse()?,
-    })
+    debug!("Asking for one-time password, attempt {}", attempt);
+    events.emit(AuthStage::OtpRequested);
+    let ctx = OtpContext {
+        attempt: *attempt,
+        message: message.map(str::to_string),
+    };
+    bounded(req, LoginStage::Otp, provider.otp(&ctx)).await
 }
 
 
-async fn get_oauth_top(client: reqwest::Client, url: Url) -> crate::error::Result<(String, String, HashMap<String, String>)> {
+pub(crate) async fn post_oauth_login(req: &LoginRequest, login_url: Url, referer: &Url, input: &[(String, String)], cookies: &mut CookieJar) -> crate::error::Result<String> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(input)
+        .finish();
+    let request = HttpRequest::post(login_url.clone())
+        .default_ffxiv_headers(req)
+        .with_cookies(cookies)
+        .header(header::REFERER, HeaderValue::from_str(referer.as_str()).expect("URLs are ASCII"))
+        .header(header::CONTENT_TYPE, HeaderValue::from_static("application/x-www-form-urlencoded"))
+        .body(body);
+
+    let response = send_with_retry(&*req.transport, &req.retry_policy, false, request).await?;
+    cookies.store_response(&login_url, &response.headers);
+    Ok(response.text())
+}
 
-    let builder = client.get(url)
-        .default_ffxiv_headers()
-        .header(header::COOKIE, "_rsid=\"\"");
 
+pub(crate) async fn get_oauth_top(req: &LoginRequest, url: Url, login_path: LoginPath, cookies: &mut CookieJar) -> crate::error::Result<LoginForm> {
+    let request = HttpRequest::get(url.clone())
+        .default_ffxiv_headers(req)
+        .with_cookies(cookies);
 
-    let text = builder.send().await?.text().await?;
+    let response = send_with_retry(&*req.transport, &req.retry_policy, true, request).await?;
+    cookies.store_response(&url, &response.headers);
+    let text = response.text();
 
-    if text.contains("window.external.user(\"restartup\");") {
-        return Err(Error::Restartup)
+    if external_user_calls(&text).iter().any(|call| call == "restartup") {
+        // a Steam login is told to restart when the Steam account has no Square Enix ID yet
+        return Err(match login_path {
+            LoginPath::Global => Error::Restartup,
+            LoginPath::Steam => Error::SteamNotLinked,
+        })
     }
-    let document = Html::parse_document(&text);
-    let form_selector = Selector::parse("form[name=mainForm]").unwrap();
-    let input_selector = Selector::parse("input").unwrap();
 
+    LoginForm::parse(&text, &req.protocol)
+}
 
-    //extract form elements from "mainForm"
-    if let Some(form) = document.select(&form_selector).next() {
-        let action = form.value().attr("action").unwrap_or_default().to_string();
-        let method = form.value().attr("method").unwrap_or_default().to_string();
-        let mut form_data = HashMap::new();
 
-        for input in form.select(&input_selector) {
-            if let Some(name) = input.value().attr("name") {
-                if let Some(value) = input.value().attr("value") {
-                    form_data.insert(name.to_string(), value.to_string());
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+    use reqwest::StatusCode;
+    use crate::events::AuthEvent;
+    use crate::prelude::LauncherFingerprint;
+    use crate::transport::{HttpRequest, HttpResponse, MemoryTransport};
+
+    fn received_stages(receiver: &mut tokio::sync::mpsc::UnboundedReceiver<AuthEvent>) -> Vec<AuthStage> {
+        let mut stages = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            stages.push(event.stage);
         }
-        Ok((action, method, form_data))
-    } else {
-        Err(Error::MissingLoginForm)
+        stages
     }
 
-}
-
-
-fn extract_launch_params(html: &str) -> Option<HashMap<String, String>> {
-    const FULL_START: &str = r#"window.external.user(""#;
-    const END: &str = r#"");"#;
+    #[tokio::test]
+    async fn test_login_with_memory_transport() {
+        let transport = MemoryTransport::new();
+        let form = r#"<form action="login.send" method="post" name="mainForm">
+            <input type="hidden" name="_STORED_" value="token">
+        </form>"#;
+        transport.push_response(HttpResponse::new(StatusCode::OK, form)
+            .header(header::SET_COOKIE, HeaderValue::from_static("_rsid=abc; Path=/")));
+        transport.push_page(include_str!("test_content.html"));
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let req = LoginRequest::new(transport.clone())
+            .with_username("user".to_string())
+            .with_password("password")
+            .with_event_handler(sender);
+        // as the providers do, finish the events with the result
+        let events = AuthEvents::new(&req);
+        let res = get_oauth_login(req.clone(), None, &events).await;
+        events.finish(&res);
+        let res = res.unwrap();
+        assert_eq!(res.max_expansion, 5);
+        assert_eq!(res.cookies().get("_rsid").unwrap().value(), "abc");
+
+        assert_eq!(received_stages(&mut receiver), [AuthStage::FetchingLoginPage, AuthStage::SubmittingCredentials, AuthStage::SessionRegistered, AuthStage::Done]);
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].headers[header::COOKIE], "_rsid=\"\"");
+        assert_eq!(requests[1].headers[header::COOKIE], "_rsid=abc");
+        assert_eq!(requests[1].url.as_str(), "https://ffxiv-login.square-enix.com/oauth/ffxivarr/login/login.send");
+        let body = String::from_utf8(requests[1].body.clone().unwrap()).unwrap();
+        assert!(body.contains("_STORED_=token") && body.contains("sqexid=user"));
+
+        // a rejected password fails after submitting
+        transport.push_page(form);
+        transport.push_page(r#"window.external.user("login=auth,ng,err,Wrong ID or password.");"#);
+        let events = AuthEvents::new(&req);
+        let res = get_oauth_login(req, None, &events).await;
+        events.finish(&res);
+        assert!(matches!(res, Err(Error::LoginFailureMessage(_))));
+        assert_eq!(received_stages(&mut receiver), [AuthStage::FetchingLoginPage, AuthStage::SubmittingCredentials, AuthStage::Failed]);
+    }
 
-    html.match_indices(FULL_START)
-        .find(|(start_idx, _)| !is_in_comment(html, *start_idx))
-        .and_then(|(start_idx, _)| {
-            let params_start = start_idx + FULL_START.len();
-            html[params_start..]
-                .find(END)
-                .map(|end| html[params_start..params_start + end].to_string())
-        })
-        .and_then(|params_str| {
-            // Check if it starts with "login=" and parse accordingly
-            info!("Extracted launch params {:?}", params_str);
-            if params_str.starts_with("login=") {
-                let params_str = &params_str["login=".len()..];
-                parse_login_params(params_str)
-            } else {
-                None
+    const LOGIN_FORM: &str = r#"<form action="login.send" method="post" name="mainForm">
+        <input type="hidden" name="_STORED_" value="token">
+    </form>"#;
+    const OTP_REJECTED: &str = r#"window.external.user("login=auth,ng,err,Please enter your one-time password.");"#;
+
+    /// Logs in with an OTP provider that hands out `00000<attempt>` and returns the result, the
+    /// contexts the provider was asked with and the one-time passwords that were submitted.
+    async fn otp_login(transport: MemoryTransport) -> (crate::error::Result<LoginResponse>, Vec<OtpContext>, Vec<String>) {
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let provider = {
+            let asked = asked.clone();
+            move |ctx: &OtpContext| {
+                asked.lock().unwrap().push(ctx.clone());
+                Some(format!("00000{}", ctx.attempt))
             }
-        })
-}
-
-/// parse_login_params converts a login=key,value,key,value,key,value to a dictionary.
-fn parse_login_params(params_str: &str) -> Option<HashMap<String, String>> {
-    let parts: Vec<&str> = params_str.split(',').collect();
-
-    // Need at least "login=XXX" and one more part
-    if parts.len() < 2 {
-        return None;
+        };
+        let req = LoginRequest::new(transport.clone())
+            .with_username("user".to_string())
+            .with_password("password")
+            .with_otp_provider(provider);
+        let events = AuthEvents::new(&req);
+        let res = get_oauth_login(req, None, &events).await;
+
+        let submitted = transport.requests().iter()
+            .filter_map(|request| request.body.as_ref())
+            .map(|body| url::form_urlencoded::parse(body)
+                .find(|(key, _)| key == "otppw")
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_default())
+            .collect();
+        let asked = asked.lock().unwrap().clone();
+        (res, asked, submitted)
     }
 
-    let mut result = HashMap::new();
+    #[tokio::test]
+    async fn test_otp_resubmit() {
+        let transport = MemoryTransport::new();
+        transport.push_page(LOGIN_FORM);
+        transport.push_page(OTP_REJECTED);
+        transport.push_page(include_str!("test_content.html"));
+
+        let (res, asked, submitted) = otp_login(transport).await;
+        assert_eq!(res.unwrap().max_expansion, 5);
+        assert_eq!(asked.iter().map(|ctx| ctx.attempt).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(asked[0].message, None);
+        assert_eq!(asked[1].message.as_deref(), Some("Please enter your one-time password."));
+        assert_eq!(submitted, ["000001", "000002"]);
+    }
 
-    for chunk in parts.chunks(2) {
-        if chunk.len() == 2 {
-            let key = chunk[0].to_string();
-            let value = chunk[1].to_string();
-            result.insert(key, value);
+    #[tokio::test]
+    async fn test_otp_attempts_exhausted() {
+        let transport = MemoryTransport::new();
+        transport.push_page(LOGIN_FORM);
+        for _ in 0..MAX_OTP_ATTEMPTS {
+            transport.push_page(OTP_REJECTED);
         }
+
+        let (res, asked, submitted) = otp_login(transport).await;
+        assert!(matches!(res, Err(Error::InvalidOtp { rejected: MAX_OTP_ATTEMPTS, .. })));
+        assert_eq!(asked.iter().map(|ctx| ctx.attempt).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(submitted, ["000001", "000002", "000003"]);
     }
 
-    Some(result)
-}
+    /// Logs in through a `MemoryTransport` imitating `fingerprint` and returns the login page
+    /// request and the login form submit.
+    async fn sent_requests(fingerprint: LauncherFingerprint) -> Vec<HttpRequest> {
+        let transport = MemoryTransport::new();
+        transport.push_page(r#"<form action="login.send" method="post" name="mainForm">
+            <input type="hidden" name="_STORED_" value="token">
+        </form>"#);
+        transport.push_page(include_str!("test_content.html"));
+
+        let req = LoginRequest::new(transport.clone())
+            .with_username("user".to_string())
+            .with_password("password")
+            .with_computer_id("fc01010101".parse().unwrap())
+            .with_fingerprint(fingerprint);
+        let events = AuthEvents::new(&req);
+        get_oauth_login(req, None, &events).await.unwrap();
+        transport.requests()
+    }
 
-#[instrument(skip(html), ret)]
-fn is_in_comment(html: &str, position: usize) -> bool {
-    let before_position = &html[..position];
+    /// The headers of `request`, in the order they were sent.
+    fn headers(request: &HttpRequest) -> Vec<(String, String)> {
+        request.headers.iter()
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+            .collect()
+    }
 
-    // Check for single-line comment
-    if let Some(line_start) = before_position.rfind('\n') {
-        let line_prefix = &html[line_start + 1..position];
-        if line_prefix.trim_start().starts_with("//") {
-            return true;
-        }
-    } else {
-        // First line case
-        if before_position.trim_start().starts_with("//") {
-            return true;
-        }
+    fn owned(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
     }
-    // false
-    // Check for multi-line comment
-    let last_comment_start = before_position.rfind("/*");
-    let last_comment_end = before_position.rfind("*/");
 
-    match (last_comment_start, last_comment_end) {
-        (Some(start), Some(end)) => start > end, // Inside comment if last /* is after last */
-        (Some(_), None) => true,                 // Inside comment if /* found but no closing */
-        _ => false,                              // Not in comment
+    /// The headers the login form submit adds after the launcher's, for the login page at `top`.
+    fn submit_headers(top: &str) -> Vec<(String, String)> {
+        owned(&[("referer", top), ("content-type", "application/x-www-form-urlencoded")])
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn test_extract_launch_params() {
-        let content = include_str!("test_content.html");
+    const WINDOWS_HEADERS: &[(&str, &str)] = &[
+        ("accept", "image/gif, image/jpeg, image/pjpeg, application/x-ms-application, application/xaml+xml, application/x-ms-xbap, */*"),
+        ("accept-encoding", "gzip, deflate"),
+        ("accept-language", "en-US"),
+        ("user-agent", "SQEXAuthor/2.0.0(Windows 6.2; ja-jp; fc01010101)"),
+        ("connection", "Keep-Alive"),
+        ("upgrade-insecure-requests", "true"),
+        ("cookie", "_rsid=\"\""),
+    ];
+
+    const TOP_URL: &str = "https://ffxiv-login.square-enix.com/oauth/ffxivarr/login/top?lng=en&isft=0&cssmode=1&isnew=1&launchver=3";
+
+    #[tokio::test]
+    async fn test_windows_requests() {
+        let sent = sent_requests(LauncherFingerprint::windows()).await;
+        assert_eq!(sent[0].url.as_str(), TOP_URL);
+        assert_eq!(headers(&sent[0]), owned(WINDOWS_HEADERS));
+        assert_eq!(headers(&sent[1]), [owned(WINDOWS_HEADERS), submit_headers(TOP_URL)].concat());
+    }
 
-        let res = extract_launch_params(content);
-        assert!(res.is_some());
+    #[tokio::test]
+    async fn test_mac_requests() {
+        let mac = owned(&[
+            ("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+            ("accept-encoding", "gzip, deflate"),
+            ("accept-language", "en-us"),
+            ("user-agent", "macSQEXAuthor/2.0.0(MacOSX; ja-jp)"),
+            ("connection", "keep-alive"),
+            ("cookie", "_rsid=\"\""),
+        ]);
+        let sent = sent_requests(LauncherFingerprint::mac()).await;
+        assert_eq!(sent[0].url.as_str(), TOP_URL);
+        assert_eq!(headers(&sent[0]), mac);
+        assert_eq!(headers(&sent[1]), [mac, submit_headers(TOP_URL)].concat());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_steam_requests() {
+        let top = format!("{TOP_URL}&issteam=1");
+        let sent = sent_requests(LauncherFingerprint::steam()).await;
+        assert_eq!(sent[0].url.as_str(), top);
+        assert_eq!(headers(&sent[0]), owned(WINDOWS_HEADERS));
+        assert_eq!(headers(&sent[1]), [owned(WINDOWS_HEADERS), submit_headers(&top)].concat());
+
+        // a ticket comes after the launcher's parameters, which already say it comes from Steam
+        let ticket = || Ticket { text: "ticket".to_string(), length: 6 };
+        let req = LoginRequest::new(MemoryTransport::new()).with_fingerprint(LauncherFingerprint::steam());
+        assert_eq!(get_oauth_top_url(&req, Some(ticket())).unwrap().as_str(), format!("{top}&session_ticket=ticket&ticket_size=6"));
+        let req = LoginRequest::new(MemoryTransport::new());
+        assert_eq!(get_oauth_top_url(&req, Some(ticket())).unwrap().as_str(), format!("{top}&session_ticket=ticket&ticket_size=6"));
+    }
+}
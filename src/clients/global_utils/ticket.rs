@@ -1,4 +1,3 @@
-use std::env;
 use std::io::{Cursor, Write, Seek, SeekFrom};
 #[cfg(feature = "blowfish")]
 use blowfish::Blowfish;
@@ -8,14 +7,15 @@ use blowfish::cipher::BlockEncrypt;
 use blowfish::cipher::generic_array::GenericArray;
 #[cfg(feature = "blowfish")]
 use blowfish::cipher::KeyInit;
-#[cfg(feature = "steamworks")]
-use steamworks::{AppId, Client, SIResult, networking_types::{NetworkingIdentity}};
-#[cfg(feature = "steam")]
+#[cfg(feature = "steam_shared")]
+use crate::clients::steam_api::SteamApi;
+#[cfg(feature = "steam_shared")]
 use tracing::trace;
-#[cfg(feature = "steamworks")]
+#[cfg(feature = "steam_shared")]
 use crate::clients::global_utils::CrtRand;
 #[cfg(feature = "base64")]
 use base64::{engine::general_purpose::URL_SAFE};
+#[cfg(feature = "base64")]
 use base64::Engine;
 use crate::error::{Error, Result};
 
@@ -25,24 +25,19 @@ pub(crate) struct Ticket {
     pub(crate) length: u16,
 }
 
+#[cfg(feature = "steam_shared")]
 const FUCKED_GARBAGE_ALPHABET:&[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz-_";
+#[cfg(feature = "steam_shared")]
 const SPLIT_SIZE: usize = 300;
 impl Ticket {
-    #[cfg(feature = "steam")]
-    pub(crate) fn new(app_id: AppId) -> Result<Ticket>{
-        unsafe {
-            env::set_var("SteamAppId", 1.to_string());
-            env::set_var("SteamGameId", 1.to_string());
-        }
-
-        let c = Client::init_app(app_id)?;
-
-        let ticket = c.user().authentication_session_ticket(NetworkingIdentity::new());
-        let time = c.utils().get_server_real_time();
+    #[cfg(feature = "steam_shared")]
+    pub(crate) fn new(steam: &dyn SteamApi) -> Result<Ticket>{
+        let ticket = steam.session_ticket();
+        let time = steam.server_real_time();
 
-        Ticket::parse(ticket.1, time)
+        Ticket::parse(ticket, time)
     }
-    #[cfg(feature = "steam")]
+    #[cfg(feature = "steam_shared")]
     pub fn parse(ticket: Vec<u8>, mut time: u32) -> Result<Ticket> {
         // Adjust time
         time -= 5;
@@ -53,7 +48,7 @@ impl Ticket {
             .map(|b| format!("{:02x}", b))
             .collect::<String>();
 
-        let mut raw_ticket_bytes = ticket_string.into_bytes();
+        let raw_ticket_bytes = ticket_string.into_bytes();
 
         // Add null terminator
         let mut raw_ticket = vec![0u8; raw_ticket_bytes.len() + 1];
@@ -91,10 +86,10 @@ impl Ticket {
         ]);
 
         // Generate garbage bytes
-        for i in 0..num_random_bytes {
+        for garbage_byte in garbage.iter_mut() {
             let rand_index = ((fucked_sum.wrapping_add(rand.next())) & 0x3F) as usize;
             let rand_char = FUCKED_GARBAGE_ALPHABET[rand_index];
-            garbage[i] = rand_char;
+            *garbage_byte = rand_char;
             fucked_sum = fucked_sum.wrapping_add(rand_char as u32);
         }
 
@@ -140,7 +135,7 @@ impl Ticket {
     }
 }
 
-#[cfg(feature = "steam")]
+#[cfg(feature = "steam_shared")]
 fn encrypt(blowfish: Blowfish, input: &[u8], output: &mut [u8]) {
     for s in (0..input.len()).step_by(8) {
         let input = GenericArray::from_slice(&input[s..s + 8]);
@@ -152,6 +147,7 @@ fn encrypt(blowfish: Blowfish, input: &[u8], output: &mut [u8]) {
 
 
 // Helper function to split string into chunks
+#[cfg(feature = "steam_shared")]
 fn chunks_upto(s: &str, chunk_size: usize) -> impl Iterator<Item = &str> {
     (0..s.len())
         .step_by(chunk_size)
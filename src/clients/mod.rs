@@ -12,10 +12,14 @@ pub use cn::*;
 mod kr;
 #[cfg(feature = "kr")]
 pub use kr::*;
-#[cfg(feature = "steam")]
+#[cfg(feature = "steam_shared")]
 mod steam;
-#[cfg(feature = "steam")]
+#[cfg(feature = "steam_shared")]
 pub use steam::*;
+#[cfg(feature = "steam_shared")]
+pub(crate) mod steam_api;
 
-#[cfg(any(feature = "steam", feature="global"))]
-pub(crate) mod global_utils;
\ No newline at end of file
+#[cfg(any(feature = "steam_shared", feature="global"))]
+pub(crate) mod global_utils;
+#[cfg(any(feature = "steam_shared", feature="global"))]
+pub use global_utils::{LoginForm, OAuthForm, PageSummary, OAuthState, OtpChallenge, SubmittedForm};
\ No newline at end of file
@@ -1,17 +1,24 @@
-use steamworks::{AppId, Client};
-use tracing::instrument;
-use crate::clients::global_utils::{get_oauth_login, Ticket};
-use crate::prelude::{AuthProvider, LoginRequest, LoginResponse};
+use tracing::{debug, info, instrument};
+use crate::clients::global_utils::{get_oauth_login, get_oauth_top, get_oauth_top_url, OAuthState, Ticket};
+use crate::clients::steam_api::{self, SteamApi};
+use crate::cookies::CookieJar;
+use crate::error::Error;
+use crate::events::{AuthEvents, AuthStage};
+use crate::timeouts::{bounded, LoginStage};
+use crate::prelude::{AuthProvider, LoginPath, LoginRequest, LoginResponse, NoCredentials, SteamIdentity, TypedAuthProvider};
 
 #[derive(Debug, Default)]
-struct SteamClient{
+pub struct SteamClient{
 
 }
 
-#[derive(Debug, Default)]
-#[allow(dead_code)]
-struct GlobalClient{
-
+/// Whether the Steam account is linked to a Square Enix ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SteamLink {
+    /// The Square Enix ID the login will use.
+    Linked(String),
+    /// The account has to be linked on the Square Enix website before it can log in.
+    NotLinked,
 }
 
 
@@ -20,18 +27,214 @@ struct GlobalClient{
 
  */
 
-const FREE_TRIAL_APP_ID: AppId = AppId(312060);
-const STEAM_APP_ID: AppId = AppId(39210);
+const FREE_TRIAL_APP_ID: u32 = 312060;
+const STEAM_APP_ID: u32 = 39210;
+
+impl SteamClient {
+    /// Initializes Steam for the FFXIV app the logged-in user owns and returns whether it is the
+    /// free trial. Passing `Some` in `is_free_trial` skips the detection and forces that app.
+    fn init_steam(is_free_trial: Option<bool>) -> crate::error::Result<(Box<dyn SteamApi>, bool)> {
+        Self::init_steam_with(is_free_trial, steam_api::init_app)
+    }
+
+    fn init_steam_with<F>(is_free_trial: Option<bool>, init_app: F) -> crate::error::Result<(Box<dyn SteamApi>, bool)>
+    where F: Fn(u32) -> crate::error::Result<Box<dyn SteamApi>> {
+        if let Some(is_free_trial) = is_free_trial {
+            let app_id = if is_free_trial { FREE_TRIAL_APP_ID } else { STEAM_APP_ID };
+            return Ok((init_app(app_id)?, is_free_trial));
+        }
+
+        let steam = match init_app(STEAM_APP_ID) {
+            Ok(steam) => steam,
+            // Steam refuses to initialize as an app the user has no license for
+            Err(e) if steam_api::may_lack_license(&e) => {
+                debug!("Steam did not initialize as the full game: {e}");
+                let Ok(steam) = init_app(FREE_TRIAL_APP_ID) else {
+                    return Err(e);
+                };
+                if steam.is_subscribed_app(FREE_TRIAL_APP_ID) {
+                    info!("Steam user only owns the free trial");
+                    return Ok((steam, true));
+                }
+                if !steam.is_subscribed_app(STEAM_APP_ID) {
+                    return Err(Error::SteamAppNotOwned);
+                }
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        // the full game wins when the user owns both apps
+        if steam.is_subscribed_app(STEAM_APP_ID) {
+            return Ok((steam, false));
+        }
+        if !steam.is_subscribed_app(FREE_TRIAL_APP_ID) {
+            return Err(Error::SteamAppNotOwned);
+        }
+
+        info!("Steam user only owns the free trial");
+        // the ticket is bound to the app Steam was initialized with, so start over as the free trial
+        drop(steam);
+        Ok((init_app(FREE_TRIAL_APP_ID)?, true))
+    }
+
+    /// Creates a session ticket and records the detected app in `req`. Steam blocks, so this runs
+    /// on the blocking thread pool to keep it within the request's timeout and cancellation.
+    async fn ticket(req: &mut LoginRequest) -> crate::error::Result<(Ticket, SteamIdentity)> {
+        let is_free_trial = req.is_free_trial;
+        let (ticket, identity, is_free_trial) = bounded(req, LoginStage::SteamTicket, async move {
+            tokio::task::spawn_blocking(move || Self::create_ticket(is_free_trial)).await
+                .map_err(|e| Error::IoError(e.into()))?
+        }).await?;
+
+        req.is_free_trial = Some(is_free_trial);
+        Ok((ticket, identity))
+    }
+
+    fn create_ticket(is_free_trial: Option<bool>) -> crate::error::Result<(Ticket, SteamIdentity, bool)> {
+        let (steam, is_free_trial) = Self::init_steam(is_free_trial)?;
+
+        let identity = SteamIdentity {
+            steam_id: steam.steam_id(),
+            persona_name: steam.persona_name(),
+            app_id: if is_free_trial { FREE_TRIAL_APP_ID } else { STEAM_APP_ID },
+        };
+        Ok((Ticket::new(steam.as_ref())?, identity, is_free_trial))
+    }
+
+    /// Fetches the login form without submitting it, to find out which Square Enix ID the
+    /// Steam account is linked to.
+    #[instrument(name="SteamClient::linked_account", ret, err)]
+    pub async fn linked_account(&self, mut req: LoginRequest) -> crate::error::Result<SteamLink> {
+        let (ticket, _) = Self::ticket(&mut req).await?;
+        let url = get_oauth_top_url(&req, Some(ticket))?;
+
+        match bounded(&req, LoginStage::LoginPage, get_oauth_top(&req, url.clone(), LoginPath::Steam, &mut CookieJar::for_login(&url))).await {
+            Ok(form) => Ok(form.linked_account
+                .map(SteamLink::Linked)
+                .unwrap_or(SteamLink::NotLinked)),
+            Err(Error::SteamNotLinked) => Ok(SteamLink::NotLinked),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fetches the login form for a step-wise login, see [`OAuthState`].
+    #[instrument(name="SteamClient::begin", skip(req), err)]
+    pub async fn begin(&self, req: &LoginRequest) -> crate::error::Result<OAuthState> {
+        let mut req = req.clone();
+        let steam = Self::ticket(&mut req).await?;
+        OAuthState::fetch(&req, Some(steam)).await
+    }
+}
 
 #[async_trait::async_trait]
-impl AuthProvider for GlobalClient {
-    #[instrument(name="GlobalClient::authenticate", ret, err)]
+impl AuthProvider for SteamClient {
+    #[instrument(name="SteamClient::authenticate", ret, err)]
     async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
-        let ticket = match req.is_free_trial {
-            Some(true) => Ticket::new(FREE_TRIAL_APP_ID)?,
-            _ => Ticket::new(STEAM_APP_ID)?,
-        };
+        let events = AuthEvents::new(&req);
+        let res = self.authenticate_with(req, &events).await;
+        events.finish(&res);
+        res
+    }
+}
+
+impl SteamClient {
+    pub(crate) async fn authenticate_with(&self, mut req: LoginRequest, events: &AuthEvents) -> crate::error::Result<LoginResponse> {
+        let steam = Self::ticket(&mut req).await?;
+        events.emit(AuthStage::SteamTicketGenerated);
+        get_oauth_login(req, Some(steam), events).await
+    }
+}
+
+impl TypedAuthProvider for SteamClient {
+    type Credentials = NoCredentials;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Steam user owning `owned`.
+    struct FakeSteam {
+        owned: Vec<u32>,
+    }
+
+    impl SteamApi for FakeSteam {
+        fn is_subscribed_app(&self, app_id: u32) -> bool {
+            self.owned.contains(&app_id)
+        }
+        fn session_ticket(&self) -> Vec<u8> {
+            Vec::new()
+        }
+        fn server_real_time(&self) -> u32 {
+            0
+        }
+        fn steam_id(&self) -> u64 {
+            0
+        }
+        fn persona_name(&self) -> String {
+            String::new()
+        }
+    }
+
+    #[cfg(feature = "steam")]
+    fn no_license() -> Error {
+        Error::SteamApiInit(steamworks::SteamAPIInitError::FailedGeneric("no license".to_string()))
+    }
+    #[cfg(all(feature = "steam_dynamic", not(feature = "steam")))]
+    fn no_license() -> Error {
+        Error::SteamInit { result: crate::error::SteamInitResult::FailedGeneric, message: "no license".to_string() }
+    }
+
+    #[cfg(feature = "steam")]
+    fn unavailable() -> Error {
+        Error::SteamApiInit(steamworks::SteamAPIInitError::NoSteamClient("Steam is not running".to_string()))
+    }
+    #[cfg(all(feature = "steam_dynamic", not(feature = "steam")))]
+    fn unavailable() -> Error {
+        Error::SteamLibraryNotFound("libsteam_api.so: cannot open shared object file".to_string())
+    }
+
+    /// Initializes as every app in `initializes` and fails with `no_license` for the others.
+    fn init_for(initializes: &[u32], owned: &[u32]) -> impl Fn(u32) -> crate::error::Result<Box<dyn SteamApi>> {
+        let (initializes, owned) = (initializes.to_vec(), owned.to_vec());
+        move |app_id| {
+            if initializes.contains(&app_id) {
+                Ok(Box::new(FakeSteam { owned: owned.clone() }) as Box<dyn SteamApi>)
+            } else {
+                Err(no_license())
+            }
+        }
+    }
+
+    #[test]
+    fn test_init_steam() {
+        let both = [STEAM_APP_ID, FREE_TRIAL_APP_ID];
+        let (_, free_trial) = SteamClient::init_steam_with(None, init_for(&both, &both)).unwrap();
+        assert!(!free_trial);
+        let trial = [FREE_TRIAL_APP_ID];
+        let (_, free_trial) = SteamClient::init_steam_with(None, init_for(&trial, &trial)).unwrap();
+        assert!(free_trial);
+
+        // Steam initialized but the user owns neither app
+        assert!(matches!(SteamClient::init_steam_with(None, init_for(&both, &[])), Err(Error::SteamAppNotOwned)));
+        // Steam never initialized, so ownership is unknown and the error is kept
+        let res = SteamClient::init_steam_with(None, init_for(&[], &[]));
+        assert_eq!(res.err().map(|e| e.to_string()), Some(no_license().to_string()));
+    }
+
+    #[test]
+    fn test_init_steam_unavailable() {
+        let res = SteamClient::init_steam_with(None, |_| Err(unavailable()));
+        assert_eq!(res.err().map(|e| e.to_string()), Some(unavailable().to_string()));
+    }
 
-        get_oauth_login(req, Some(ticket)).await
+    #[cfg(all(feature = "steam_dynamic", not(feature = "steam")))]
+    #[test]
+    fn test_init_steam_without_library() {
+        // only meaningful where the Steamworks library is not installed, like CI
+        if !matches!(steam_api::init_app(FREE_TRIAL_APP_ID), Err(Error::SteamLibraryNotFound(_))) {
+            return;
+        }
+        assert!(matches!(SteamClient::init_steam(None), Err(Error::SteamLibraryNotFound(_))));
     }
 }
@@ -0,0 +1,191 @@
+use std::env;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use libloading::Library;
+use tracing::{debug, instrument};
+use crate::clients::steam_api::SteamApi;
+use crate::error::{Error, SteamInitResult};
+
+#[cfg(all(windows, target_pointer_width = "64"))]
+const LIBRARY_NAME: &str = "steam_api64.dll";
+#[cfg(all(windows, target_pointer_width = "32"))]
+const LIBRARY_NAME: &str = "steam_api.dll";
+#[cfg(target_os = "macos")]
+const LIBRARY_NAME: &str = "libsteam_api.dylib";
+#[cfg(not(any(windows, target_os = "macos")))]
+const LIBRARY_NAME: &str = "libsteam_api.so";
+
+/// `ESteamAPIInitResult::k_ESteamAPIInitResult_OK`
+const INIT_OK: c_int = 0;
+/// `ESteamAPIInitResult::k_ESteamAPIInitResult_NoSteamClient`
+const INIT_NO_STEAM_CLIENT: c_int = 2;
+/// `ESteamAPIInitResult::k_ESteamAPIInitResult_VersionMismatch`
+const INIT_VERSION_MISMATCH: c_int = 3;
+/// `SteamNetworkingIdentity` is 136 bytes, all zero is the invalid (unknown) identity
+const NETWORKING_IDENTITY_SIZE: usize = 136;
+
+type SteamErrMsg = [c_char; 1024];
+
+/// The flat API functions resolved from `libsteam_api`, see `steam_api_flat.h`.
+struct SteamLibrary {
+    init_flat: unsafe extern "C" fn(*mut SteamErrMsg) -> c_int,
+    shutdown: unsafe extern "C" fn(),
+    user: unsafe extern "C" fn() -> *mut c_void,
+    utils: unsafe extern "C" fn() -> *mut c_void,
+    apps: unsafe extern "C" fn() -> *mut c_void,
+    friends: unsafe extern "C" fn() -> *mut c_void,
+    get_auth_session_ticket: unsafe extern "C" fn(*mut c_void, *mut c_void, c_int, *mut u32, *const c_void) -> u32,
+    get_server_real_time: unsafe extern "C" fn(*mut c_void) -> u32,
+    is_subscribed_app: unsafe extern "C" fn(*mut c_void, u32) -> bool,
+    get_steam_id: unsafe extern "C" fn(*mut c_void) -> u64,
+    get_persona_name: unsafe extern "C" fn(*mut c_void) -> *const c_char,
+    // keeps the function pointers above valid
+    _library: Library,
+}
+
+static STEAM_LIBRARY: OnceLock<Result<SteamLibrary, String>> = OnceLock::new();
+
+impl SteamLibrary {
+    /// Loads the library on first use and keeps it loaded for the rest of the process.
+    fn get() -> crate::error::Result<&'static SteamLibrary> {
+        STEAM_LIBRARY.get_or_init(SteamLibrary::load)
+            .as_ref()
+            .map_err(|e| Error::SteamLibraryNotFound(e.clone()))
+    }
+
+    fn load() -> Result<SteamLibrary, String> {
+        // prefer the copy shipped next to the binary, then let the OS search its library paths
+        let mut candidates = Vec::new();
+        if let Some(dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(|p| p.to_path_buf())) {
+            candidates.push(dir.join(LIBRARY_NAME));
+        }
+        candidates.push(PathBuf::from(LIBRARY_NAME));
+        SteamLibrary::load_from(candidates)
+    }
+
+    #[instrument]
+    fn load_from(candidates: Vec<PathBuf>) -> Result<SteamLibrary, String> {
+        let mut errors = Vec::new();
+        for candidate in candidates {
+            // SAFETY: libsteam_api has no initialization routines that depend on the caller
+            match unsafe { Library::new(&candidate) } {
+                Ok(library) => {
+                    debug!("Loaded {}", candidate.display());
+                    return SteamLibrary::resolve(library)
+                        .map_err(|e| format!("{}: {}", candidate.display(), e));
+                }
+                // the error names the path already
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        Err(errors.join("; "))
+    }
+
+    fn resolve(library: Library) -> Result<SteamLibrary, libloading::Error> {
+        // SAFETY: the signatures match steam_api_flat.h of the SDK version steamworks 0.12 targets
+        unsafe {
+            Ok(SteamLibrary {
+                init_flat: *library.get(b"SteamAPI_InitFlat\0")?,
+                shutdown: *library.get(b"SteamAPI_Shutdown\0")?,
+                user: *library.get(b"SteamAPI_SteamUser_v023\0")?,
+                utils: *library.get(b"SteamAPI_SteamUtils_v010\0")?,
+                apps: *library.get(b"SteamAPI_SteamApps_v008\0")?,
+                friends: *library.get(b"SteamAPI_SteamFriends_v018\0")?,
+                get_auth_session_ticket: *library.get(b"SteamAPI_ISteamUser_GetAuthSessionTicket\0")?,
+                get_server_real_time: *library.get(b"SteamAPI_ISteamUtils_GetServerRealTime\0")?,
+                is_subscribed_app: *library.get(b"SteamAPI_ISteamApps_BIsSubscribedApp\0")?,
+                get_steam_id: *library.get(b"SteamAPI_ISteamUser_GetSteamID\0")?,
+                get_persona_name: *library.get(b"SteamAPI_ISteamFriends_GetPersonaName\0")?,
+                _library: library,
+            })
+        }
+    }
+}
+
+pub(crate) struct DynamicSteam {
+    library: &'static SteamLibrary,
+}
+
+impl DynamicSteam {
+    pub(crate) fn init_app(app_id: u32) -> crate::error::Result<DynamicSteam> {
+        let library = SteamLibrary::get()?;
+
+        // SAFETY: same as steamworks' Client::init_app, Steam reads the app id from the environment
+        unsafe {
+            env::set_var("SteamAppId", app_id.to_string());
+            env::set_var("SteamGameId", app_id.to_string());
+        }
+
+        let mut err_msg: SteamErrMsg = [0; 1024];
+        let result = unsafe { (library.init_flat)(&mut err_msg) };
+        if result != INIT_OK {
+            let message = unsafe { CStr::from_ptr(err_msg.as_ptr()) };
+            let result = match result {
+                INIT_NO_STEAM_CLIENT => SteamInitResult::NoSteamClient,
+                INIT_VERSION_MISMATCH => SteamInitResult::VersionMismatch,
+                _ => SteamInitResult::FailedGeneric,
+            };
+            return Err(Error::SteamInit { result, message: message.to_string_lossy().into_owned() });
+        }
+
+        Ok(DynamicSteam { library })
+    }
+}
+
+impl SteamApi for DynamicSteam {
+    fn is_subscribed_app(&self, app_id: u32) -> bool {
+        unsafe { (self.library.is_subscribed_app)((self.library.apps)(), app_id) }
+    }
+
+    fn session_ticket(&self) -> Vec<u8> {
+        let mut ticket = vec![0u8; 1024];
+        let mut ticket_len = 0u32;
+        let identity = [0u64; NETWORKING_IDENTITY_SIZE / 8];
+        unsafe {
+            (self.library.get_auth_session_ticket)(
+                (self.library.user)(),
+                ticket.as_mut_ptr().cast(),
+                ticket.len() as c_int,
+                &mut ticket_len,
+                identity.as_ptr().cast(),
+            );
+        }
+        ticket.truncate(ticket_len as usize);
+        ticket
+    }
+
+    fn server_real_time(&self) -> u32 {
+        unsafe { (self.library.get_server_real_time)((self.library.utils)()) }
+    }
+
+    fn steam_id(&self) -> u64 {
+        unsafe { (self.library.get_steam_id)((self.library.user)()) }
+    }
+
+    fn persona_name(&self) -> String {
+        // the name is owned by Steam and stays valid until the next call
+        let name = unsafe { CStr::from_ptr((self.library.get_persona_name)((self.library.friends)())) };
+        name.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for DynamicSteam {
+    fn drop(&mut self) {
+        unsafe { (self.library.shutdown)() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_library() {
+        let missing = PathBuf::from("/nonexistent").join(LIBRARY_NAME);
+        let Err(e) = SteamLibrary::load_from(vec![missing.clone()]) else {
+            panic!("loaded a library that does not exist");
+        };
+        assert_eq!(e.matches(&*missing.to_string_lossy()).count(), 1);
+    }
+}
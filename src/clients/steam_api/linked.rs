@@ -0,0 +1,35 @@
+use steamworks::{AppId, Client};
+use steamworks::networking_types::NetworkingIdentity;
+use crate::clients::steam_api::SteamApi;
+
+pub(crate) struct LinkedSteam {
+    client: Client,
+}
+
+impl LinkedSteam {
+    pub(crate) fn init_app(app_id: u32) -> crate::error::Result<LinkedSteam> {
+        Ok(LinkedSteam { client: Client::init_app(AppId(app_id))? })
+    }
+}
+
+impl SteamApi for LinkedSteam {
+    fn is_subscribed_app(&self, app_id: u32) -> bool {
+        self.client.apps().is_subscribed_app(AppId(app_id))
+    }
+
+    fn session_ticket(&self) -> Vec<u8> {
+        self.client.user().authentication_session_ticket(NetworkingIdentity::new()).1
+    }
+
+    fn server_real_time(&self) -> u32 {
+        self.client.utils().get_server_real_time()
+    }
+
+    fn steam_id(&self) -> u64 {
+        self.client.user().steam_id().raw()
+    }
+
+    fn persona_name(&self) -> String {
+        self.client.friends().name()
+    }
+}
@@ -0,0 +1,45 @@
+//! The few Steamworks calls the Steam login needs, either linked at build time through
+//! `steamworks` (`steam` feature) or loaded from `libsteam_api` on first use (`steam_dynamic`).
+//! The linked library wins when both features are enabled.
+
+#[cfg(feature = "steam")]
+mod linked;
+#[cfg(all(feature = "steam_dynamic", not(feature = "steam")))]
+mod dynamic;
+
+pub(crate) trait SteamApi {
+    fn is_subscribed_app(&self, app_id: u32) -> bool;
+    /// A fresh auth session ticket for the logged-in user.
+    fn session_ticket(&self) -> Vec<u8>;
+    fn server_real_time(&self) -> u32;
+    /// The SteamID64 of the logged-in user.
+    fn steam_id(&self) -> u64;
+    fn persona_name(&self) -> String;
+}
+
+/// Initializes Steam as `app_id`. Steam shuts down again when the returned value is dropped.
+#[cfg(feature = "steam")]
+pub(crate) fn init_app(app_id: u32) -> crate::error::Result<Box<dyn SteamApi>> {
+    Ok(Box::new(linked::LinkedSteam::init_app(app_id)?))
+}
+
+/// Initializes Steam as `app_id`. Steam shuts down again when the returned value is dropped.
+#[cfg(all(feature = "steam_dynamic", not(feature = "steam")))]
+pub(crate) fn init_app(app_id: u32) -> crate::error::Result<Box<dyn SteamApi>> {
+    Ok(Box::new(dynamic::DynamicSteam::init_app(app_id)?))
+}
+
+/// Whether Steam failed to initialize in a way that can mean the user has no license for the app,
+/// as opposed to Steam or its library not being available.
+#[cfg(feature = "steam")]
+pub(crate) fn may_lack_license(e: &crate::error::Error) -> bool {
+    matches!(e, crate::error::Error::SteamApiInit(steamworks::SteamAPIInitError::FailedGeneric(_)))
+}
+
+/// Whether Steam failed to initialize in a way that can mean the user has no license for the app,
+/// as opposed to Steam or its library not being available.
+#[cfg(all(feature = "steam_dynamic", not(feature = "steam")))]
+pub(crate) fn may_lack_license(e: &crate::error::Error) -> bool {
+    matches!(e, crate::error::Error::SteamInit { result: crate::error::SteamInitResult::FailedGeneric, .. })
+}
+
@@ -0,0 +1,200 @@
+use std::fmt::{Debug, Formatter};
+use std::time::{Duration, SystemTime};
+use reqwest::header::{HeaderMap, HeaderValue, SET_COOKIE};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
+
+/// A cookie set by the login server, or seeded by the login itself.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    /// Set without a `Domain` attribute, only sent back to the exact host.
+    host_only: bool,
+    path: String,
+    secure: bool,
+    expires: Option<SystemTime>,
+}
+
+impl Debug for Cookie {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cookie")
+            .field("name", &self.name)
+            .field("value", &"[REDACTED]")
+            .field("domain", &self.domain)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Cookie {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= SystemTime::now())
+    }
+
+    /// Whether the cookie is sent with a request to `url`, see RFC 6265 section 5.4.
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let domain_matches = host == self.domain
+            || (!self.host_only && host.strip_suffix(&self.domain).is_some_and(|prefix| prefix.ends_with('.')));
+        let path = url.path();
+        let path_matches = path == self.path
+            || (path.starts_with(&self.path) && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+
+        domain_matches && path_matches && (!self.secure || url.scheme() == "https") && !self.is_expired()
+    }
+}
+
+/// The cookies of one login. Every login starts with its own jar, so logins of different accounts
+/// on one HTTP client do not see each other's cookies. The jar of a finished login is available
+/// from [`LoginResponse::cookies`](crate::prelude::LoginResponse::cookies) for follow-up requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        CookieJar::default()
+    }
+
+    /// The jar a login starts with. The launcher sends an empty `_rsid` with its first request,
+    /// the server replaces it when it sets its own.
+    pub(crate) fn for_login(top_url: &Url) -> Self {
+        let mut jar = CookieJar::new();
+        jar.set(top_url, "_rsid", "\"\"");
+        jar
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.iter().filter(|cookie| !cookie.is_expired())
+    }
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.iter().find(|cookie| cookie.name == name)
+    }
+
+    /// Sets a host-only cookie for all paths of the host of `url`.
+    pub fn set(&mut self, url: &Url, name: &str, value: &str) {
+        self.insert(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: url.host_str().unwrap_or_default().to_ascii_lowercase(),
+            host_only: true,
+            path: "/".to_string(),
+            secure: false,
+            expires: None,
+        });
+    }
+
+    /// Stores one `Set-Cookie` header of a response to `url`. Cookies for other domains and
+    /// malformed headers are ignored, an expired cookie removes the stored one.
+    pub fn set_cookie(&mut self, url: &Url, header: &str) {
+        let Ok(parsed) = cookie::Cookie::parse(header) else {
+            debug!("Ignoring malformed Set-Cookie header");
+            return;
+        };
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+            return;
+        };
+
+        let (domain, host_only) = match parsed.domain().map(|d| d.trim_start_matches('.').to_ascii_lowercase()) {
+            Some(domain) if host == domain || host.ends_with(&format!(".{domain}")) => (domain, false),
+            Some(domain) => {
+                debug!(%domain, "Ignoring cookie for another domain");
+                return;
+            }
+            None => (host, true),
+        };
+        let path = match parsed.path() {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            // the default path is the directory of the request path
+            _ => url.path().rfind('/').filter(|&i| i > 0).map_or("/".to_string(), |i| url.path()[..i].to_string()),
+        };
+        let expires = match parsed.max_age() {
+            Some(max_age) => Some(SystemTime::now() + Duration::from_secs(max_age.whole_seconds().max(0) as u64)),
+            None => parsed.expires_datetime().map(SystemTime::from),
+        };
+
+        trace!(name = parsed.name(), %domain, %path, "Storing cookie");
+        self.insert(Cookie {
+            name: parsed.name().to_string(),
+            value: parsed.value().to_string(),
+            domain,
+            host_only,
+            path,
+            secure: parsed.secure().unwrap_or(false),
+            expires,
+        });
+    }
+
+    /// Stores every `Set-Cookie` header of a response to `url`.
+    pub fn store_response(&mut self, url: &Url, headers: &HeaderMap) {
+        for header in headers.get_all(SET_COOKIE) {
+            if let Ok(header) = header.to_str() {
+                self.set_cookie(url, header);
+            }
+        }
+    }
+
+    /// The `Cookie` header for a request to `url`, longer paths first.
+    pub fn header_for(&self, url: &Url) -> Option<HeaderValue> {
+        let mut cookies: Vec<_> = self.iter().filter(|cookie| cookie.matches(url)).collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+
+        let header = cookies.iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&header).ok()
+    }
+
+    /// Replaces the cookie with the same name, domain and path.
+    fn insert(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        if !cookie.is_expired() {
+            self.cookies.push(cookie);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_jar() {
+        let url = Url::parse("https://ffxiv-login.square-enix.com/oauth/ffxivarr/login/top").unwrap();
+        let mut jar = CookieJar::new();
+        jar.set(&url, "_rsid", "\"\"");
+        jar.set_cookie(&url, "_rsid=abc; Path=/; Secure; HttpOnly");
+        jar.set_cookie(&url, "lang=en; Domain=.square-enix.com; Path=/");
+        jar.set_cookie(&url, "other=1; Domain=example.com");
+
+        assert_eq!(jar.header_for(&url).unwrap(), "_rsid=abc; lang=en");
+        let other_host = Url::parse("https://secure.square-enix.com/").unwrap();
+        assert_eq!(jar.header_for(&other_host).unwrap(), "lang=en");
+
+        jar.set_cookie(&url, "lang=; Domain=square-enix.com; Path=/; Max-Age=0");
+        assert!(jar.get("lang").is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::process::Stdio;
+use std::time::Duration;
+use secure_string::SecureString;
+use tokio::process::Command;
+use tracing::{debug, instrument};
+use zeroize::Zeroize;
+use crate::error::Error;
+
+/// Supplies the account password when the login form is submitted, so it does not have to live
+/// in the [`LoginRequest`](crate::prelude::LoginRequest). Set it with
+/// [`LoginRequest::with_credential_provider`](crate::prelude::LoginRequest::with_credential_provider).
+#[async_trait::async_trait]
+pub trait CredentialProvider: Send + Sync {
+    /// The Square Enix ID, if the provider knows it. A username set on the request wins.
+    fn username(&self) -> Option<String> {
+        None
+    }
+
+    async fn password(&self) -> crate::error::Result<SecureString>;
+}
+
+/// Runs a command and uses the first line it prints as the password, e.g. `pass show ffxiv` or
+/// `op read op://vault/ffxiv/password`. The command is not run through a shell.
+#[derive(Debug, Clone)]
+pub struct CommandCredentialProvider {
+    program: OsString,
+    args: Vec<OsString>,
+    username: Option<String>,
+    timeout: Duration,
+}
+
+impl CommandCredentialProvider {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new<S>(program: S) -> Self
+    where S: Into<OsString> {
+        CommandCredentialProvider {
+            program: program.into(),
+            args: Vec::new(),
+            username: None,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+    pub fn arg<S>(mut self, arg: S) -> Self
+    where S: Into<OsString> {
+        self.args.push(arg.into());
+        self
+    }
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where I: IntoIterator<Item = S>, S: Into<OsString> {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+    pub fn with_username(mut self, username: String) -> Self {
+        self.username = Some(username);
+        self
+    }
+    /// How long the command may run before it is killed. Interactive unlock prompts count
+    /// towards it, see [`CommandCredentialProvider::DEFAULT_TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl CredentialProvider for CommandCredentialProvider {
+    fn username(&self) -> Option<String> {
+        self.username.clone()
+    }
+
+    #[instrument(name="CommandCredentialProvider::password", skip(self), fields(program = ?self.program), err)]
+    async fn password(&self) -> crate::error::Result<SecureString> {
+        let child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // dropping the future on timeout kills the child
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output()).await
+            .map_err(|_| Error::CredentialCommandTimeout(self.timeout))??;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::CredentialCommandFailed(format!("{}: {}", output.status, stderr.trim())));
+        }
+        debug!("Credential command succeeded");
+
+        // move the first line into the secure string instead of copying it, and wipe the rest of
+        // the output, which the secure string does not cover once truncated
+        let mut stdout = output.stdout;
+        let end = stdout.iter().position(|b| *b == b'\r' || *b == b'\n').unwrap_or(stdout.len());
+        stdout[end..].zeroize();
+        stdout.truncate(end);
+        let password = match String::from_utf8(stdout) {
+            Ok(password) => password,
+            Err(e) => {
+                e.into_bytes().zeroize();
+                return Err(Error::CredentialCommandFailed("output is not valid UTF-8".to_string()));
+            }
+        };
+        if password.is_empty() {
+            return Err(Error::CredentialCommandFailed("no password printed".to_string()));
+        }
+
+        Ok(SecureString::from(password))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_credential_provider() {
+        let provider = CommandCredentialProvider::new("sh").args(["-c", "printf 'hunter2\\nurl: example.com\\n'"]);
+        assert_eq!(provider.password().await.unwrap().unsecure(), "hunter2");
+
+        let provider = CommandCredentialProvider::new("sh").args(["-c", "echo locked >&2; exit 1"]);
+        assert!(matches!(provider.password().await, Err(Error::CredentialCommandFailed(e)) if e.contains("locked")));
+
+        let provider = CommandCredentialProvider::new("sleep").arg("5").with_timeout(Duration::from_millis(50));
+        assert!(matches!(provider.password().await, Err(Error::CredentialCommandTimeout(_))));
+    }
+}
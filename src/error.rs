@@ -12,6 +12,16 @@ pub enum Error {
     #[error("Request error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
+    /// the server answered with a `4xx` or `5xx` status
+    #[error("HTTP status {0}")]
+    HttpStatus(reqwest::StatusCode),
+    /// a custom `HttpTransport` failed to send a request
+    #[error("Transport error ({kind}): {message}")]
+    Transport {
+        kind: crate::transport::TransportErrorKind,
+        message: String,
+    },
+
     #[error("Parse error: {0}")]
     ParseError(#[from] url::ParseError),
 
@@ -24,13 +34,87 @@ pub enum Error {
     /// this error is raised when using the global login but you needed to use the steam login
     #[error("restartup, but not Steam")]
     Restartup,
+    /// the Steam account is not linked to a Square Enix ID yet
+    #[error("Steam account is not linked to a Square Enix ID")]
+    SteamNotLinked,
+    /// a Steam login was needed but this build was compiled without Steam support
+    #[error("Steam login required, but Steam support is not available")]
+    SteamUnavailable,
 
+    #[deprecated(note = "a page without the login form is reported as `UnexpectedPage`")]
     #[error("Missing Login Form")]
     MissingLoginForm,
+    /// the login form does not have the shape this crate submits: a POST form with the `_STORED_`
+    /// token. The protocol version itself is not checked, see `OAuthProtocol`
+    #[error("Login protocol mismatch: {0}")]
+    ProtocolMismatch(String),
+    /// the server answered with a page that has no login form, e.g. a maintenance notice
+    #[cfg(any(feature="steam_shared", feature="global"))]
+    #[error("Unexpected page: {0}")]
+    UnexpectedPage(crate::prelude::PageSummary),
     #[error("LoginFailure")]
     LoginFailure,
     #[error("LoginFailure: {0}")]
     LoginFailureMessage(String),
+    /// the login result page did not hand the launcher this parameter
+    #[error("Missing launch parameter {0}")]
+    MissingLaunchParam(&'static str),
+    #[error("Invalid launch parameter {key}: {value:?}")]
+    InvalidLaunchParam {
+        key: &'static str,
+        value: String,
+    },
+
+    /// the account has a one-time password but none was given
+    #[error("One-time password required")]
+    OtpRequired,
+    /// the server rejected the one-time password; `rejected` counts the submits it rejected during
+    /// the login, as an OTP provider can be asked again up to `MAX_OTP_ATTEMPTS` times
+    #[error("Invalid one-time password: {message}")]
+    InvalidOtp { message: String, rejected: u32 },
+
+    /// the command of a `CommandCredentialProvider` did not finish in time
+    #[error("Credential command timed out after {0:?}")]
+    CredentialCommandTimeout(std::time::Duration),
+    #[error("Credential command failed: {0}")]
+    CredentialCommandFailed(String),
+
+    #[error("Timed out {0}")]
+    Timeout(crate::timeouts::LoginStage),
+    #[error("Cancelled while {0}")]
+    Cancelled(crate::timeouts::LoginStage),
+
+    /// the `LockoutGuard` refused to try again after too many failed logins
+    #[error("Locked out locally after {failures} failed logins for {account}")]
+    LockedOutLocally {
+        account: String,
+        failures: u32,
+        /// when the cooldown allows the next attempt, `None` until the guard is reset
+        retry_after: Option<std::time::Duration>,
+    },
+
+    /// the `ProviderRegistry` has no provider of this name
+    #[error("Unknown provider {0}")]
+    UnknownProvider(String),
+    #[error("The {provider} provider needs the `{feature}` feature")]
+    ProviderNotCompiled {
+        provider: &'static str,
+        feature: &'static str,
+    },
+    #[error("The {0} provider is not implemented yet")]
+    ProviderNotImplemented(&'static str),
+    #[error("Invalid provider option: {0}")]
+    InvalidProviderOption(String),
+
+    /// the `AccountManager` has no account of this name
+    #[error("Unknown account {0}")]
+    UnknownAccount(String),
+
+    #[error("The fallback chain has no providers")]
+    EmptyFallbackChain,
+
+    #[error("Invalid computer ID {0}")]
+    InvalidComputerId(String),
 
     #[error("Missing Username")]
     MissingUsername,
@@ -43,8 +127,34 @@ pub enum Error {
     #[cfg(feature="steam")]
     #[error(transparent)]
     SteamApiInit(#[from] SteamAPIInitError),
+    /// the logged-in Steam user owns neither the full game nor the free trial
+    #[cfg(feature="steam_shared")]
+    #[error("Steam user does not own FINAL FANTASY XIV")]
+    SteamAppNotOwned,
+
+    /// the Steamworks library could not be loaded at runtime
+    #[cfg(feature="steam_dynamic")]
+    #[error("Steamworks library not found: {0}")]
+    SteamLibraryNotFound(String),
+    /// the runtime loaded Steamworks library failed to initialize, e.g. because Steam is not running
+    #[cfg(feature="steam_dynamic")]
+    #[error("Steamworks initialization failed ({result:?}): {message}")]
+    SteamInit {
+        result: SteamInitResult,
+        message: String,
+    },
 
     #[error(transparent)]
     #[cfg(feature="blowfish")]
     InvalidLength(#[from]InvalidLength)
-}
\ No newline at end of file
+}
+
+/// Why the runtime loaded Steamworks library failed to initialize, its `ESteamAPIInitResult`.
+#[cfg(feature="steam_dynamic")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SteamInitResult {
+    /// e.g. the user has no license for the app
+    FailedGeneric,
+    NoSteamClient,
+    VersionMismatch,
+}
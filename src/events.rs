@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+use crate::prelude::LoginRequest;
+
+/// The stages a login goes through, in order. Not every login reaches every stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthStage {
+    FetchingLoginPage,
+    SteamTicketGenerated,
+    SubmittingCredentials,
+    /// The one-time password is being asked from the request's OTP provider.
+    OtpRequested,
+    /// The server issued a session id.
+    SessionRegistered,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthEvent {
+    pub stage: AuthStage,
+    /// Time since the login started.
+    pub elapsed: Duration,
+}
+
+/// Receives the progress of a login. Set it with
+/// [`LoginRequest::with_event_handler`](crate::prelude::LoginRequest::with_event_handler).
+/// It is called inline, so it should not block.
+pub trait AuthEventHandler: Send + Sync {
+    fn on_event(&self, event: AuthEvent);
+}
+
+impl<F> AuthEventHandler for F
+where F: Fn(AuthEvent) + Send + Sync {
+    fn on_event(&self, event: AuthEvent) {
+        self(event)
+    }
+}
+
+/// Events are dropped once the receiver is gone.
+impl AuthEventHandler for tokio::sync::mpsc::UnboundedSender<AuthEvent> {
+    fn on_event(&self, event: AuthEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Events are dropped once the receiver is gone.
+impl AuthEventHandler for std::sync::mpsc::Sender<AuthEvent> {
+    fn on_event(&self, event: AuthEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Reports the events of one login to the handler of its request.
+pub(crate) struct AuthEvents {
+    handler: Option<Arc<dyn AuthEventHandler>>,
+    started: Instant,
+}
+
+impl AuthEvents {
+    pub(crate) fn new(req: &LoginRequest) -> Self {
+        AuthEvents {
+            handler: req.event_handler.clone(),
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn emit(&self, stage: AuthStage) {
+        let event = AuthEvent { stage, elapsed: self.started.elapsed() };
+        debug!(?event, "Auth event");
+        if let Some(handler) = &self.handler {
+            handler.on_event(event);
+        }
+    }
+
+    /// Emits [`AuthStage::Done`] or [`AuthStage::Failed`] for the result of the login.
+    pub(crate) fn finish<T>(&self, res: &crate::error::Result<T>) {
+        self.emit(if res.is_ok() { AuthStage::Done } else { AuthStage::Failed });
+    }
+}
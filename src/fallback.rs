@@ -0,0 +1,141 @@
+use tracing::{info, instrument};
+use crate::error::Error;
+use crate::prelude::{AuthProvider, BoxedAuthProvider, LoginRequest, LoginResponse};
+
+/// Tries providers in order, e.g. Steam and then Global for users who do not know whether their
+/// account is Steam-linked.
+///
+/// The next provider is only tried when the previous one could not be used for this account, see
+/// [`FallbackChain::should_fall_back`]. Rejected credentials end the chain, trying them again with
+/// another provider would count towards Square Enix's lockout.
+///
+/// ```no_run
+/// # use ff_auth::prelude::*;
+/// # async fn run(req: LoginRequest) -> Result<(), Error> {
+/// let chain = FallbackChain::new()
+///     .with_provider("steam", SteamClient::default())
+///     .with_provider("global", GlobalClient::default());
+/// let login = chain.login(req).await?;
+/// println!("Logged in with {}", login.provider);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct FallbackChain {
+    providers: Vec<(String, BoxedAuthProvider)>,
+    fall_back: Option<fn(&Error) -> bool>,
+}
+
+/// A successful login of a [`FallbackChain`].
+#[derive(Debug, Clone)]
+pub struct FallbackLogin {
+    /// The name of the provider that logged in.
+    pub provider: String,
+    pub response: LoginResponse,
+}
+
+impl FallbackChain {
+    pub fn new() -> Self {
+        FallbackChain::default()
+    }
+    pub fn with_provider<N, P>(self, name: N, provider: P) -> Self
+    where N: Into<String>, P: AuthProvider + Send + Sync + 'static {
+        self.with_boxed(name, Box::new(provider))
+    }
+    /// Adds a provider built by the [`ProviderRegistry`](crate::prelude::ProviderRegistry).
+    pub fn with_boxed<N>(mut self, name: N, provider: BoxedAuthProvider) -> Self
+    where N: Into<String> {
+        self.providers.push((name.into(), provider));
+        self
+    }
+    /// Replaces [`FallbackChain::should_fall_back`] with `fall_back`.
+    pub fn with_fall_back_on(mut self, fall_back: fn(&Error) -> bool) -> Self {
+        self.fall_back = Some(fall_back);
+        self
+    }
+
+    /// Whether an error means the account needs another login method: the global login answered
+    /// restartup, or Steam is not available, not linked or does not own the game.
+    pub fn should_fall_back(e: &Error) -> bool {
+        match e {
+            Error::Restartup | Error::SteamUnavailable | Error::SteamNotLinked => true,
+            Error::ProviderNotCompiled { .. } | Error::ProviderNotImplemented(_) => true,
+            #[cfg(feature = "steam_shared")]
+            Error::SteamAppNotOwned => true,
+            #[cfg(feature = "steam_dynamic")]
+            Error::SteamLibraryNotFound(_) | Error::SteamInit { .. } => true,
+            #[cfg(feature = "steam")]
+            Error::SteamApiInit(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Logs in with the first provider that can, returning its name with the response. Returns the
+    /// error of the last provider tried.
+    #[instrument(name="FallbackChain::login", skip_all, err)]
+    pub async fn login(&self, req: LoginRequest) -> crate::error::Result<FallbackLogin> {
+        let fall_back = self.fall_back.unwrap_or(Self::should_fall_back);
+        let mut last_error = None;
+
+        for (name, provider) in &self.providers {
+            match provider.authenticate(req.clone()).await {
+                Ok(response) => {
+                    info!(provider = %name, "Logged in");
+                    return Ok(FallbackLogin { provider: name.clone(), response });
+                }
+                Err(e) if fall_back(&e) => {
+                    info!(provider = %name, error = %e, "Trying the next provider");
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or(Error::EmptyFallbackChain))
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthProvider for FallbackChain {
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        Ok(self.login(req).await?.response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Failing(fn() -> Error);
+
+    #[async_trait::async_trait]
+    impl AuthProvider for Failing {
+        async fn authenticate(&self, _req: LoginRequest) -> crate::error::Result<LoginResponse> {
+            Err((self.0)())
+        }
+    }
+
+    struct Succeeding;
+
+    #[async_trait::async_trait]
+    impl AuthProvider for Succeeding {
+        async fn authenticate(&self, _req: LoginRequest) -> crate::error::Result<LoginResponse> {
+            Ok(LoginResponse::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_chain() {
+        let req = LoginRequest::new(reqwest::Client::new());
+
+        let chain = FallbackChain::new()
+            .with_provider("global", Failing(|| Error::Restartup))
+            .with_provider("steam", Succeeding);
+        assert_eq!(chain.login(req.clone()).await.unwrap().provider, "steam");
+
+        let chain = FallbackChain::new()
+            .with_provider("global", Failing(|| Error::LoginFailure))
+            .with_provider("steam", Succeeding);
+        assert!(matches!(chain.login(req).await, Err(Error::LoginFailure)));
+    }
+}
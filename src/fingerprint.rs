@@ -0,0 +1,127 @@
+use reqwest::header::{self, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use crate::identity::ComputerId;
+
+/// The launcher a [`LauncherFingerprint`] imitates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LauncherPlatform {
+    /// The Windows launcher, whose login page runs in an embedded Internet Explorer.
+    #[default]
+    Windows,
+    /// The macOS launcher, whose login page runs in WebKit.
+    MacOs,
+    /// The Windows launcher started by Steam. It sends the same headers as the Windows launcher
+    /// and marks the login page URL with `issteam=1`.
+    Steam,
+}
+
+/// The platform and locale segment of the launcher's user agent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserAgent {
+    pub platform: String,
+    pub locale: String,
+}
+
+impl Default for UserAgent {
+    /// What the Windows launcher sends.
+    fn default() -> Self {
+        UserAgent {
+            platform: "Windows 6.2".to_string(),
+            locale: "ja-jp".to_string(),
+        }
+    }
+}
+
+impl UserAgent {
+    pub fn with_platform<S>(mut self, platform: S) -> Self
+    where S: Into<String> {
+        self.platform = platform.into();
+        self
+    }
+    pub fn with_locale<S>(mut self, locale: S) -> Self
+    where S: Into<String> {
+        self.locale = locale.into();
+        self
+    }
+}
+
+/// The headers and extra query parameters of the login requests, imitating one launcher. The
+/// server answers differently depending on them, so the headers are sent in the order of
+/// [`LauncherFingerprint::headers`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LauncherFingerprint {
+    pub platform: LauncherPlatform,
+    pub user_agent: UserAgent,
+}
+
+impl Default for LauncherFingerprint {
+    fn default() -> Self {
+        LauncherFingerprint::windows()
+    }
+}
+
+impl LauncherFingerprint {
+    pub fn windows() -> Self {
+        LauncherFingerprint {
+            platform: LauncherPlatform::Windows,
+            user_agent: UserAgent::default(),
+        }
+    }
+    pub fn mac() -> Self {
+        LauncherFingerprint {
+            platform: LauncherPlatform::MacOs,
+            user_agent: UserAgent::default().with_platform("MacOSX"),
+        }
+    }
+    pub fn steam() -> Self {
+        LauncherFingerprint {
+            platform: LauncherPlatform::Steam,
+            user_agent: UserAgent::default(),
+        }
+    }
+    pub fn with_user_agent(mut self, user_agent: UserAgent) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// The `User-Agent` header. The macOS launcher does not send a computer ID.
+    pub fn user_agent(&self, computer_id: &ComputerId) -> String {
+        let UserAgent { platform, locale } = &self.user_agent;
+        match self.platform {
+            LauncherPlatform::Windows | LauncherPlatform::Steam => format!("SQEXAuthor/2.0.0({platform}; {locale}; {computer_id})"),
+            LauncherPlatform::MacOs => format!("macSQEXAuthor/2.0.0({platform}; {locale})"),
+        }
+    }
+
+    /// The headers of every login request, in the order they are sent.
+    pub fn headers(&self, computer_id: &ComputerId) -> Vec<(HeaderName, HeaderValue)> {
+        let user_agent = HeaderValue::from_str(&self.user_agent(computer_id))
+            .unwrap_or_else(|_| HeaderValue::from_static("SQEXAuthor/2.0.0"));
+        match self.platform {
+            LauncherPlatform::Windows | LauncherPlatform::Steam => vec![
+                (header::ACCEPT, HeaderValue::from_static("image/gif, image/jpeg, image/pjpeg, application/x-ms-application, application/xaml+xml, application/x-ms-xbap, */*")),
+                (header::ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate")),
+                (header::ACCEPT_LANGUAGE, HeaderValue::from_static("en-US")),
+                (header::USER_AGENT, user_agent),
+                (header::CONNECTION, HeaderValue::from_static("Keep-Alive")),
+                (header::UPGRADE_INSECURE_REQUESTS, HeaderValue::from_static("true")),
+            ],
+            LauncherPlatform::MacOs => vec![
+                (header::ACCEPT, HeaderValue::from_static("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")),
+                (header::ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate")),
+                (header::ACCEPT_LANGUAGE, HeaderValue::from_static("en-us")),
+                (header::USER_AGENT, user_agent),
+                (header::CONNECTION, HeaderValue::from_static("keep-alive")),
+            ],
+        }
+    }
+
+    /// Query parameters added to the login page URL after the protocol parameters. A Steam login
+    /// adds its ticket parameters on top of these.
+    pub fn query_params(&self) -> Vec<(&'static str, &'static str)> {
+        match self.platform {
+            LauncherPlatform::Windows | LauncherPlatform::MacOs => Vec::new(),
+            LauncherPlatform::Steam => vec![("issteam", "1")],
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::error::Error;
+
+/// The computer ID the launcher sends in its user agent: four bytes identifying the machine,
+/// preceded by a checksum byte that makes all five bytes sum to zero. Written as 10 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ComputerId([u8; 5]);
+
+impl ComputerId {
+    /// Builds an ID from the four machine bytes, adding the checksum.
+    pub fn from_machine_bytes(machine: [u8; 4]) -> Self {
+        let sum = machine.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        ComputerId([sum.wrapping_neg(), machine[0], machine[1], machine[2], machine[3]])
+    }
+
+    /// A random ID, e.g. for a new profile.
+    pub fn random() -> Self {
+        ComputerId::from_machine_bytes(rand::thread_rng().r#gen())
+    }
+
+    /// Reads the ID stored at `path`, or stores a new random one there, so a profile keeps its ID
+    /// across runs and hosts.
+    pub fn load_or_generate<P>(path: P) -> crate::error::Result<Self>
+    where P: AsRef<Path> {
+        match fs::read_to_string(&path) {
+            Ok(text) => text.trim().parse(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let id = ComputerId::random();
+                fs::write(&path, id.to_string())?;
+                Ok(id)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn bytes(&self) -> [u8; 5] {
+        self.0
+    }
+}
+
+impl Display for ComputerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+/// Parses and validates 10 hex digits with a correct checksum byte.
+impl FromStr for ComputerId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidComputerId(format!("{s:?}: {reason}"));
+        if s.len() != 10 || !s.is_ascii() {
+            return Err(invalid("expected 10 hex digits"));
+        }
+
+        let mut bytes = [0u8; 5];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| invalid("expected 10 hex digits"))?;
+        }
+        let id = ComputerId::from_machine_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+        if id.0 != bytes {
+            return Err(invalid("wrong checksum byte"));
+        }
+        Ok(id)
+    }
+}
+
+impl Serialize for ComputerId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ComputerId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_computer_id() {
+        let id = ComputerId::random();
+        assert_eq!(id.to_string().parse::<ComputerId>().unwrap(), id);
+        assert_eq!(id.bytes().iter().fold(0u8, |sum, b| sum.wrapping_add(*b)), 0);
+
+        assert_eq!("fc01010101".parse::<ComputerId>().unwrap().bytes(), [0xfc, 1, 1, 1, 1]);
+        assert!(matches!("fd01010101".parse::<ComputerId>(), Err(Error::InvalidComputerId(_))));
+        assert!(matches!("fc010101".parse::<ComputerId>(), Err(Error::InvalidComputerId(_))));
+        assert!(matches!("zz01010101".parse::<ComputerId>(), Err(Error::InvalidComputerId(_))));
+    }
+}
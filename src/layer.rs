@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash, Hasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument};
+use crate::prelude::{AuthProvider, CredentialProvider, LauncherPlatform, LoginPath, LoginRequest, LoginResponse, TypedAuthProvider};
+
+/// Wraps a provider in another one that adds shared behavior, like tower's `Layer`.
+pub trait AuthLayer<P> {
+    type Provider;
+
+    fn layer(&self, inner: P) -> Self::Provider;
+}
+
+/// Adds [`AuthProviderExt::layer`] to every provider.
+pub trait AuthProviderExt: AuthProvider + Sized {
+    fn layer<L>(self, layer: L) -> L::Provider
+    where L: AuthLayer<Self> {
+        layer.layer(self)
+    }
+}
+
+impl<P> AuthProviderExt for P
+where P: AuthProvider {}
+
+/// The layer that leaves the provider as it is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity;
+
+impl<P> AuthLayer<P> for Identity {
+    type Provider = P;
+
+    fn layer(&self, inner: P) -> P {
+        inner
+    }
+}
+
+/// Applies `inner` first, then wraps the result in `outer`.
+#[derive(Debug, Clone)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<P, Inner, Outer> AuthLayer<P> for Stack<Inner, Outer>
+where Inner: AuthLayer<P>, Outer: AuthLayer<Inner::Provider> {
+    type Provider = Outer::Provider;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        self.outer.layer(self.inner.layer(inner))
+    }
+}
+
+/// Stacks layers in order: the first layer added sees a login first and its result last.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use ff_auth::prelude::*;
+/// let client = ProviderBuilder::new()
+///     .layer(AuditLayer::new())
+///     .layer(CacheLayer::new(Duration::from_secs(60 * 60)))
+///     .layer(LockoutLayer::new(MemoryAttemptStore::default()))
+///     .provider(GlobalClient::default());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProviderBuilder<L = Identity> {
+    layer: L,
+}
+
+impl ProviderBuilder {
+    pub fn new() -> Self {
+        ProviderBuilder { layer: Identity }
+    }
+}
+
+impl<L> ProviderBuilder<L> {
+    pub fn layer<T>(self, layer: T) -> ProviderBuilder<Stack<T, L>> {
+        ProviderBuilder {
+            layer: Stack { inner: layer, outer: self.layer },
+        }
+    }
+
+    pub fn provider<P>(&self, provider: P) -> L::Provider
+    where L: AuthLayer<P> {
+        self.layer.layer(provider)
+    }
+}
+
+/// Reuses the session of an account's last successful login until `ttl` has passed. Sessions
+/// are kept per username, password, region, free-trial flag and launcher, so a request that
+/// differs in any of them logs in again. Requests without a username, like Steam logins, are
+/// not cached unless [`CacheLayer::with_key`] names their account.
+#[derive(Clone)]
+pub struct CacheLayer {
+    ttl: Duration,
+    key: Option<Arc<KeyFn>>,
+}
+
+type KeyFn = dyn Fn(&LoginRequest) -> Option<String> + Send + Sync;
+
+impl Debug for CacheLayer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheLayer")
+            .field("ttl", &self.ttl)
+            .field("key", &self.key.is_some())
+            .finish()
+    }
+}
+
+impl CacheLayer {
+    pub fn new(ttl: Duration) -> Self {
+        CacheLayer { ttl, key: None }
+    }
+    /// Names the account of requests without a username, e.g. the Steam profile a Steam login
+    /// runs under. Returning `None` leaves the request uncached.
+    pub fn with_key<F>(mut self, key: F) -> Self
+    where F: Fn(&LoginRequest) -> Option<String> + Send + Sync + 'static {
+        self.key = Some(Arc::new(key));
+        self
+    }
+}
+
+impl<P> AuthLayer<P> for CacheLayer {
+    type Provider = CachedProvider<P>;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        CachedProvider {
+            inner,
+            ttl: self.ttl,
+            key: self.key.clone(),
+            hasher: RandomState::new(),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+pub struct CachedProvider<P> {
+    inner: P,
+    ttl: Duration,
+    key: Option<Arc<KeyFn>>,
+    /// Hashes the passwords of the keys with a per-provider random seed.
+    hasher: RandomState,
+    sessions: Mutex<HashMap<SessionKey, (Instant, LoginResponse)>>,
+}
+
+/// What a cached session is looked up by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SessionKey {
+    account: String,
+    password: PasswordKey,
+    region: Option<i8>,
+    is_free_trial: Option<bool>,
+    platform: LauncherPlatform,
+}
+
+/// The password part of a [`SessionKey`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PasswordKey {
+    None,
+    /// A hash of the password.
+    Hash(u64),
+    /// A credential provider stands for its password, which is only asked for when submitting.
+    Provider(ProviderKey),
+}
+
+/// Compares credential providers by identity. The key holds on to the provider, so its address
+/// cannot be reused by another one while the session is cached.
+#[derive(Clone)]
+struct ProviderKey(Arc<dyn CredentialProvider>);
+
+impl PartialEq for ProviderKey {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ProviderKey {}
+
+impl Hash for ProviderKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+impl Debug for ProviderKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[CredentialProvider]")
+    }
+}
+
+impl<P> CachedProvider<P> {
+    /// Drops all cached sessions, e.g. after the server rejected one.
+    pub fn clear(&self) {
+        self.sessions.lock().unwrap().clear();
+    }
+
+    fn session_key(&self, req: &LoginRequest) -> Option<SessionKey> {
+        let account = req.account_name()
+            .or_else(|| self.key.as_ref().and_then(|key| key(req)))?;
+        let password = match (&req.password, &req.credential_provider) {
+            (Some(password), _) => PasswordKey::Hash(self.hasher.hash_one(password.unsecure())),
+            (None, Some(provider)) => PasswordKey::Provider(ProviderKey(provider.clone())),
+            (None, None) => PasswordKey::None,
+        };
+        Some(SessionKey {
+            account,
+            password,
+            region: req.region,
+            is_free_trial: req.is_free_trial,
+            platform: req.fingerprint.platform,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for CachedProvider<P>
+where P: AuthProvider + Send + Sync {
+    #[instrument(name="CachedProvider::authenticate", skip_all, err)]
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        let Some(key) = self.session_key(&req) else {
+            debug!("No account to cache the session for");
+            return self.inner.authenticate(req).await;
+        };
+        if let Some((at, res)) = self.sessions.lock().unwrap().get(&key)
+            && at.elapsed() < self.ttl {
+            debug!("Using cached session");
+            return Ok(res.clone());
+        }
+
+        let res = self.inner.authenticate(req).await?;
+        self.sessions.lock().unwrap().insert(key, (Instant::now(), res.clone()));
+        Ok(res)
+    }
+}
+
+impl<P> TypedAuthProvider for CachedProvider<P>
+where P: TypedAuthProvider + Send + Sync {
+    type Credentials = P::Credentials;
+}
+
+/// Login counts and durations, updated by the providers of a [`MetricsLayer`].
+#[derive(Debug, Default)]
+pub struct LoginMetrics {
+    attempts: AtomicU64,
+    successes: AtomicU64,
+    failures: AtomicU64,
+    total_millis: AtomicU64,
+}
+
+impl LoginMetrics {
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+    pub fn successes(&self) -> u64 {
+        self.successes.load(Ordering::Relaxed)
+    }
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+    /// The time spent in all finished logins.
+    pub fn total_time(&self) -> Duration {
+        Duration::from_millis(self.total_millis.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer {
+    metrics: Arc<LoginMetrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<LoginMetrics>) -> Self {
+        MetricsLayer { metrics }
+    }
+    pub fn metrics(&self) -> Arc<LoginMetrics> {
+        self.metrics.clone()
+    }
+}
+
+impl<P> AuthLayer<P> for MetricsLayer {
+    type Provider = MeteredProvider<P>;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        MeteredProvider { inner, metrics: self.metrics.clone() }
+    }
+}
+
+pub struct MeteredProvider<P> {
+    inner: P,
+    metrics: Arc<LoginMetrics>,
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for MeteredProvider<P>
+where P: AuthProvider + Send + Sync {
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        let started = Instant::now();
+        self.metrics.attempts.fetch_add(1, Ordering::Relaxed);
+
+        let res = self.inner.authenticate(req).await;
+        let counter = if res.is_ok() { &self.metrics.successes } else { &self.metrics.failures };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.metrics.total_millis.fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        res
+    }
+}
+
+impl<P> TypedAuthProvider for MeteredProvider<P>
+where P: TypedAuthProvider + Send + Sync {
+    type Credentials = P::Credentials;
+}
+
+/// What an [`AuditLayer`] records about a finished login. Never contains secrets.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub account: Option<String>,
+    pub login_path: Option<LoginPath>,
+    pub elapsed: Duration,
+    /// The error of a failed login.
+    pub error: Option<String>,
+}
+
+/// Receives the [`AuditRecord`] of every login.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F> AuditSink for F
+where F: Fn(&AuditRecord) + Send + Sync {
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// Logs at info level with the `ff_auth::audit` target.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        info!(target: "ff_auth::audit", account = ?record.account, login_path = ?record.login_path,
+            elapsed = ?record.elapsed, error = ?record.error, "Login finished");
+    }
+}
+
+#[derive(Clone)]
+pub struct AuditLayer {
+    sink: Arc<dyn AuditSink>,
+}
+
+impl Default for AuditLayer {
+    fn default() -> Self {
+        AuditLayer::new()
+    }
+}
+
+impl AuditLayer {
+    /// Records to [`TracingAuditSink`].
+    pub fn new() -> Self {
+        AuditLayer { sink: Arc::new(TracingAuditSink) }
+    }
+    pub fn with_sink<S>(sink: S) -> Self
+    where S: AuditSink + 'static {
+        AuditLayer { sink: Arc::new(sink) }
+    }
+}
+
+impl<P> AuthLayer<P> for AuditLayer {
+    type Provider = AuditedProvider<P>;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        AuditedProvider { inner, sink: self.sink.clone() }
+    }
+}
+
+pub struct AuditedProvider<P> {
+    inner: P,
+    sink: Arc<dyn AuditSink>,
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for AuditedProvider<P>
+where P: AuthProvider + Send + Sync {
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        let started = Instant::now();
+        let account = req.account_name();
+
+        let res = self.inner.authenticate(req).await;
+        self.sink.record(&AuditRecord {
+            account,
+            login_path: res.as_ref().ok().map(LoginResponse::login_path),
+            elapsed: started.elapsed(),
+            error: res.as_ref().err().map(ToString::to_string),
+        });
+        res
+    }
+}
+
+impl<P> TypedAuthProvider for AuditedProvider<P>
+where P: TypedAuthProvider + Send + Sync {
+    type Credentials = P::Credentials;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{CommandCredentialProvider, ComputerId};
+
+    #[derive(Default)]
+    struct Counting(AtomicU64);
+
+    #[async_trait::async_trait]
+    impl AuthProvider for Counting {
+        async fn authenticate(&self, _req: LoginRequest) -> crate::error::Result<LoginResponse> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(LoginResponse::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stack() {
+        let metrics = MetricsLayer::default();
+        let client = ProviderBuilder::new()
+            .layer(metrics.clone())
+            .layer(CacheLayer::new(Duration::from_secs(60)))
+            .provider(Counting::default());
+        let req = LoginRequest::new(reqwest::Client::new()).with_username("user".to_string());
+
+        client.authenticate(req.clone()).await.unwrap();
+        client.authenticate(req.clone()).await.unwrap();
+        // the metrics layer is outside the cache and sees both logins
+        assert_eq!(metrics.metrics().attempts(), 2);
+        assert_eq!(client.inner.inner.0.load(Ordering::Relaxed), 1);
+
+        client.inner.clear();
+        client.authenticate(req).await.unwrap();
+        assert_eq!(client.inner.inner.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_key() {
+        let client = CacheLayer::new(Duration::from_secs(60)).layer(Counting::default());
+        let req = LoginRequest::new(reqwest::Client::new()).with_username("user".to_string());
+        client.authenticate(req.clone().with_password("right")).await.unwrap();
+        client.authenticate(req.clone().with_password("wrong")).await.unwrap();
+        client.authenticate(req.clone().with_password("right").with_region(1)).await.unwrap();
+        assert_eq!(client.inner.0.load(Ordering::Relaxed), 3);
+
+        // a credential provider is kept alive by the key, so another one cannot take its place
+        let provided = req.clone().with_credential_provider(CommandCredentialProvider::new("pass"));
+        client.authenticate(provided.clone()).await.unwrap();
+        client.authenticate(provided.clone()).await.unwrap();
+        assert_eq!(Arc::strong_count(provided.credential_provider.as_ref().unwrap()), 2);
+        drop(provided);
+        client.authenticate(req.clone().with_credential_provider(CommandCredentialProvider::new("pass"))).await.unwrap();
+        assert_eq!(client.inner.0.load(Ordering::Relaxed), 5);
+
+        // Steam logins have no username and are not cached without a key
+        let steam = LoginRequest::new(reqwest::Client::new());
+        client.authenticate(steam.clone()).await.unwrap();
+        client.authenticate(steam.clone()).await.unwrap();
+        assert_eq!(client.inner.0.load(Ordering::Relaxed), 7);
+
+        let client = CacheLayer::new(Duration::from_secs(60))
+            .with_key(|req| req.computer_id.map(|id| id.to_string()))
+            .layer(Counting::default());
+        let alice = steam.clone().with_computer_id(ComputerId::from_machine_bytes([1, 1, 1, 1]));
+        let bob = steam.with_computer_id(ComputerId::from_machine_bytes([2, 2, 2, 2]));
+        client.authenticate(alice.clone()).await.unwrap();
+        client.authenticate(bob).await.unwrap();
+        client.authenticate(alice).await.unwrap();
+        assert_eq!(client.inner.0.load(Ordering::Relaxed), 2);
+    }
+}
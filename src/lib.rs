@@ -1,10 +1,44 @@
 mod traits;
+#[cfg(any(feature = "steam_shared", feature = "global"))]
+mod accounts;
 mod error;
 mod clients;
+mod cookies;
+mod credentials;
+mod events;
+mod identity;
+mod fallback;
+mod fingerprint;
+mod layer;
+mod lockout;
+mod otp;
+mod protocol;
+mod registry;
+mod retry;
+mod timeouts;
+mod transport;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 
 #[allow(unused_imports)]
 pub mod prelude {
     pub use crate::traits::*;
+    #[cfg(any(feature = "steam_shared", feature = "global"))]
+    pub use crate::accounts::*;
     pub use crate::error::*;
     pub use crate::clients::*;
+    pub use crate::cookies::*;
+    pub use crate::credentials::*;
+    pub use crate::events::*;
+    pub use crate::identity::*;
+    pub use crate::fallback::*;
+    pub use crate::fingerprint::*;
+    pub use crate::layer::*;
+    pub use crate::lockout::*;
+    pub use crate::otp::*;
+    pub use crate::protocol::*;
+    pub use crate::registry::*;
+    pub use crate::retry::*;
+    pub use crate::timeouts::*;
+    pub use crate::transport::*;
 }
\ No newline at end of file
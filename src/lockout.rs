@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, warn};
+use crate::error::Error;
+use crate::prelude::{AuthLayer, AuthProvider, LoginRequest, LoginResponse, TypedAuthProvider};
+
+/// The failed logins of one account since its last successful login.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    pub failures: u32,
+    pub last_failure: Option<SystemTime>,
+}
+
+/// Keeps [`AttemptRecord`]s across logins, and with [`FileAttemptStore`] across runs. The guard
+/// calls it on the blocking thread pool, so implementations may block.
+pub trait AttemptStore: Send + Sync {
+    fn load(&self, account: &str) -> crate::error::Result<AttemptRecord>;
+    fn save(&self, account: &str, record: AttemptRecord) -> crate::error::Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryAttemptStore {
+    records: Mutex<HashMap<String, AttemptRecord>>,
+}
+
+impl AttemptStore for MemoryAttemptStore {
+    fn load(&self, account: &str) -> crate::error::Result<AttemptRecord> {
+        Ok(self.records.lock().unwrap().get(account).copied().unwrap_or_default())
+    }
+
+    fn save(&self, account: &str, record: AttemptRecord) -> crate::error::Result<()> {
+        self.records.lock().unwrap().insert(account.to_string(), record);
+        Ok(())
+    }
+}
+
+/// Stores the records of all accounts in one JSON file. Share one store per file, other processes
+/// writing the same file can overwrite each other's records.
+#[derive(Debug)]
+pub struct FileAttemptStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileAttemptStore {
+    pub fn new<P>(path: P) -> Self
+    where P: Into<PathBuf> {
+        FileAttemptStore {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self) -> crate::error::Result<HashMap<String, AttemptRecord>> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(serde_json::from_slice(&data).map_err(std::io::Error::from)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl AttemptStore for FileAttemptStore {
+    fn load(&self, account: &str) -> crate::error::Result<AttemptRecord> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(self.read()?.get(account).copied().unwrap_or_default())
+    }
+
+    fn save(&self, account: &str, record: AttemptRecord) -> crate::error::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut records = self.read()?;
+        records.insert(account.to_string(), record);
+
+        // write next to the file and rename, so a crash cannot leave a truncated file behind
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&records).map_err(std::io::Error::from)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Counts consecutive failed logins per account and refuses to try again once there were
+/// `threshold` of them, so a misconfigured script cannot get an account locked by Square Enix.
+///
+/// Rejected passwords and every rejected one-time password submit count as failures, a
+/// successful login resets the count. Logins of the same account through guards sharing a store
+/// wait for each other, so concurrent attempts cannot all pass the threshold. With a cooldown, one
+/// more attempt is allowed once the cooldown has passed since the last failure; without one, the
+/// account stays refused until [`LockoutGuard::reset`].
+/// Requests without a username, such as Steam logins, are passed through uncounted.
+pub struct LockoutGuard<P> {
+    inner: P,
+    store: Arc<dyn AttemptStore>,
+    locks: AccountLocks,
+    threshold: u32,
+    cooldown: Option<Duration>,
+}
+
+/// One lock per account, held from loading its record until the result is saved.
+type AccountLocks = Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>;
+
+impl<P> LockoutGuard<P> {
+    pub const DEFAULT_THRESHOLD: u32 = 3;
+
+    pub fn new<S>(inner: P, store: S) -> Self
+    where S: AttemptStore + 'static {
+        LockoutGuard {
+            inner,
+            store: Arc::new(store),
+            locks: AccountLocks::default(),
+            threshold: Self::DEFAULT_THRESHOLD,
+            cooldown: None,
+        }
+    }
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Forgets the failed logins of `account`, e.g. after fixing its password.
+    pub fn reset(&self, account: &str) -> crate::error::Result<()> {
+        self.store.save(account, AttemptRecord::default())
+    }
+
+    /// Returns [`Error::LockedOutLocally`] when `record` does not allow another attempt.
+    fn check(&self, account: &str, record: &AttemptRecord) -> crate::error::Result<()> {
+        if record.failures < self.threshold {
+            return Ok(());
+        }
+
+        let since_failure = record.last_failure
+            .and_then(|t| t.elapsed().ok())
+            .unwrap_or_default();
+        let retry_after = match self.cooldown {
+            Some(cooldown) if since_failure >= cooldown => return Ok(()),
+            Some(cooldown) => Some(cooldown - since_failure),
+            None => None,
+        };
+
+        Err(Error::LockedOutLocally {
+            account: account.to_string(),
+            failures: record.failures,
+            retry_after,
+        })
+    }
+
+    fn account_lock(&self, account: &str) -> Arc<tokio::sync::Mutex<()>> {
+        self.locks.lock().unwrap().entry(account.to_string()).or_default().clone()
+    }
+
+    async fn load(&self, account: &str) -> crate::error::Result<AttemptRecord> {
+        let (store, account) = (self.store.clone(), account.to_string());
+        tokio::task::spawn_blocking(move || store.load(&account)).await
+            .map_err(|e| Error::IoError(e.into()))?
+    }
+
+    async fn save(&self, account: &str, record: AttemptRecord) -> crate::error::Result<()> {
+        let (store, account) = (self.store.clone(), account.to_string());
+        tokio::task::spawn_blocking(move || store.save(&account, record)).await
+            .map_err(|e| Error::IoError(e.into()))?
+    }
+}
+
+/// Wraps providers in a [`LockoutGuard`] sharing one store.
+#[derive(Clone)]
+pub struct LockoutLayer {
+    store: Arc<dyn AttemptStore>,
+    locks: AccountLocks,
+    threshold: u32,
+    cooldown: Option<Duration>,
+}
+
+impl LockoutLayer {
+    pub fn new<S>(store: S) -> Self
+    where S: AttemptStore + 'static {
+        LockoutLayer {
+            store: Arc::new(store),
+            locks: AccountLocks::default(),
+            threshold: LockoutGuard::<()>::DEFAULT_THRESHOLD,
+            cooldown: None,
+        }
+    }
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+}
+
+impl<P> AuthLayer<P> for LockoutLayer {
+    type Provider = LockoutGuard<P>;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        LockoutGuard {
+            inner,
+            store: self.store.clone(),
+            locks: self.locks.clone(),
+            threshold: self.threshold,
+            cooldown: self.cooldown,
+        }
+    }
+}
+
+/// How many times the server rejected the submitted credentials before `e`. A bare
+/// [`Error::LoginFailure`] also comes back from pages without a login result, e.g. maintenance.
+fn failed_attempts(e: &Error) -> u32 {
+    match e {
+        Error::LoginFailureMessage(_) => 1,
+        Error::InvalidOtp { rejected, .. } => (*rejected).max(1),
+        _ => 0,
+    }
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for LockoutGuard<P>
+where P: AuthProvider + Send + Sync {
+    #[instrument(name="LockoutGuard::authenticate", skip_all, err)]
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        let Some(account) = req.account_name() else {
+            debug!("No account name, not counting the attempt");
+            return self.inner.authenticate(req).await;
+        };
+
+        let lock = self.account_lock(&account);
+        let _attempt = lock.lock().await;
+        let mut record = self.load(&account).await?;
+        self.check(&account, &record)?;
+
+        let res = self.inner.authenticate(req).await;
+        match &res {
+            Ok(_) if record.failures > 0 => self.save(&account, AttemptRecord::default()).await?,
+            Err(e) if failed_attempts(e) > 0 => {
+                record.failures += failed_attempts(e);
+                record.last_failure = Some(SystemTime::now());
+                warn!(failures = record.failures, threshold = self.threshold, "Failed login attempt");
+                self.save(&account, record).await?;
+            }
+            _ => {}
+        }
+        res
+    }
+}
+
+impl<P> TypedAuthProvider for LockoutGuard<P>
+where P: TypedAuthProvider + Send + Sync {
+    type Credentials = P::Credentials;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use super::*;
+
+    #[derive(Default)]
+    struct Rejecting {
+        attempts: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl AuthProvider for Rejecting {
+        async fn authenticate(&self, _req: LoginRequest) -> crate::error::Result<LoginResponse> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            Err(Error::LoginFailureMessage("wrong password".to_string()))
+        }
+    }
+
+    /// Rejects the one-time password `rejected` times in one login.
+    struct RejectingOtp {
+        rejected: u32,
+    }
+
+    #[async_trait::async_trait]
+    impl AuthProvider for RejectingOtp {
+        async fn authenticate(&self, _req: LoginRequest) -> crate::error::Result<LoginResponse> {
+            Err(Error::InvalidOtp { message: "wrong one-time password".to_string(), rejected: self.rejected })
+        }
+    }
+
+    fn request() -> LoginRequest {
+        LoginRequest::new(reqwest::Client::new()).with_username("user".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_lockout_guard() {
+        let guard = LockoutGuard::new(Rejecting::default(), MemoryAttemptStore::default()).with_threshold(2);
+        let req = request();
+
+        for _ in 0..2 {
+            assert!(matches!(guard.authenticate(req.clone()).await, Err(Error::LoginFailureMessage(_))));
+        }
+        assert!(matches!(guard.authenticate(req.clone()).await, Err(Error::LockedOutLocally { failures: 2, .. })));
+
+        guard.reset("user").unwrap();
+        assert!(matches!(guard.authenticate(req).await, Err(Error::LoginFailureMessage(_))));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_attempts() {
+        let guard = LockoutGuard::new(Rejecting::default(), MemoryAttemptStore::default()).with_threshold(1);
+        let (a, b, c) = tokio::join!(guard.authenticate(request()), guard.authenticate(request()), guard.authenticate(request()));
+        assert_eq!(guard.inner.attempts.load(Ordering::SeqCst), 1);
+        assert_eq!([a, b, c].iter().filter(|res| matches!(res, Err(Error::LockedOutLocally { .. }))).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cooldown() {
+        let store = MemoryAttemptStore::default();
+        store.save("user", AttemptRecord {
+            failures: 2,
+            last_failure: Some(SystemTime::now() - Duration::from_secs(120)),
+        }).unwrap();
+        let guard = LockoutGuard::new(Rejecting::default(), store)
+            .with_threshold(2)
+            .with_cooldown(Duration::from_secs(60));
+
+        // the cooldown has passed, so one more attempt goes through and starts a new cooldown
+        assert!(matches!(guard.authenticate(request()).await, Err(Error::LoginFailureMessage(_))));
+        let Err(Error::LockedOutLocally { failures: 3, retry_after: Some(retry_after), .. }) = guard.authenticate(request()).await else {
+            panic!("expected a lockout with a cooldown");
+        };
+        assert!(retry_after > Duration::from_secs(50) && retry_after <= Duration::from_secs(60));
+        assert_eq!(guard.inner.attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_otps() {
+        let guard = LockoutGuard::new(RejectingOtp { rejected: 3 }, MemoryAttemptStore::default()).with_threshold(3);
+        assert!(matches!(guard.authenticate(request()).await, Err(Error::InvalidOtp { rejected: 3, .. })));
+        assert!(matches!(guard.authenticate(request()).await, Err(Error::LockedOutLocally { failures: 3, .. })));
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt::Debug;
+use std::io::{BufRead, Write};
+use crate::error::Error;
+
+/// Why an [`OtpProvider`] is asked for a one-time password.
+#[derive(Debug, Clone)]
+pub struct OtpContext {
+    /// Counts from 1. Later attempts mean the server rejected the previous password.
+    pub attempt: u32,
+    /// The message the server showed when it asked again.
+    pub message: Option<String>,
+}
+
+/// Supplies the one-time password right before the login form is submitted, so the code is
+/// still fresh. Set it with [`LoginRequest::with_otp_provider`](crate::prelude::LoginRequest::with_otp_provider).
+#[async_trait::async_trait]
+pub trait OtpProvider: Send + Sync {
+    /// Returns the password to submit, or `None` to submit without one (or to stop retrying).
+    async fn otp(&self, ctx: &OtpContext) -> crate::error::Result<Option<String>>;
+}
+
+/// Closures work as providers.
+#[async_trait::async_trait]
+impl<F> OtpProvider for F
+where F: Fn(&OtpContext) -> Option<String> + Send + Sync {
+    async fn otp(&self, ctx: &OtpContext) -> crate::error::Result<Option<String>> {
+        Ok(self(ctx))
+    }
+}
+
+/// A code that is known up front. It is only offered once, a rejected code would be rejected again.
+#[derive(Clone)]
+pub struct StaticOtp(pub String);
+
+#[async_trait::async_trait]
+impl OtpProvider for StaticOtp {
+    async fn otp(&self, ctx: &OtpContext) -> crate::error::Result<Option<String>> {
+        Ok((ctx.attempt == 1).then(|| self.0.clone()))
+    }
+}
+
+impl Debug for StaticOtp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StaticOtp").field(&"[REDACTED]").finish()
+    }
+}
+
+/// Prompts on stderr and reads the code from stdin. An empty line submits without a code.
+#[derive(Debug, Clone, Default)]
+pub struct StdinOtp;
+
+#[async_trait::async_trait]
+impl OtpProvider for StdinOtp {
+    async fn otp(&self, ctx: &OtpContext) -> crate::error::Result<Option<String>> {
+        let ctx = ctx.clone();
+        let line = tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+            let mut stderr = std::io::stderr();
+            if let Some(message) = &ctx.message {
+                writeln!(stderr, "{}", message)?;
+            }
+            write!(stderr, "One-time password: ")?;
+            stderr.flush()?;
+
+            let mut line = String::new();
+            std::io::stdin().lock().read_line(&mut line)?;
+            Ok(line)
+        }).await.map_err(|e| Error::IoError(e.into()))??;
+
+        let otp = line.trim();
+        Ok((!otp.is_empty()).then(|| otp.to_string()))
+    }
+}
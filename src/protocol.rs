@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// The protocol parameters of the login page URL, which tell the server which launcher version
+/// is logging in. When the official launcher moves to a new version before this crate has a
+/// preset for it, set the values or add query parameters here instead of forking.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthProtocol {
+    pub launchver: u32,
+    pub cssmode: u32,
+    pub isnew: u32,
+    /// Added after the protocol parameters, in order.
+    pub extra_params: Vec<(String, String)>,
+    /// CSS selectors of the login form, tried in order. When none matches, the form with the
+    /// `_STORED_` token is used.
+    pub form_selectors: Vec<String>,
+}
+
+impl Default for OAuthProtocol {
+    fn default() -> Self {
+        OAuthProtocol::latest()
+    }
+}
+
+impl OAuthProtocol {
+    /// The protocol of the current launcher, `launchver=3`.
+    pub fn launcher_v3() -> Self {
+        OAuthProtocol {
+            launchver: 3,
+            cssmode: 1,
+            isnew: 1,
+            extra_params: Vec::new(),
+            form_selectors: vec!["form[name=mainForm]".to_string(), "form#mainForm".to_string()],
+        }
+    }
+    /// The newest preset, [`OAuthProtocol::launcher_v3`].
+    pub fn latest() -> Self {
+        OAuthProtocol::launcher_v3()
+    }
+
+    pub fn with_launchver(mut self, launchver: u32) -> Self {
+        self.launchver = launchver;
+        self
+    }
+    pub fn with_cssmode(mut self, cssmode: u32) -> Self {
+        self.cssmode = cssmode;
+        self
+    }
+    pub fn with_isnew(mut self, isnew: u32) -> Self {
+        self.isnew = isnew;
+        self
+    }
+    pub fn with_param<K, V>(mut self, key: K, value: V) -> Self
+    where K: Into<String>, V: Into<String> {
+        self.extra_params.push((key.into(), value.into()));
+        self
+    }
+    /// Tries `selector` before the other form selectors.
+    pub fn with_form_selector<S>(mut self, selector: S) -> Self
+    where S: Into<String> {
+        self.form_selectors.insert(0, selector.into());
+        self
+    }
+}
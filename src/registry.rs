@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use crate::error::Error;
+use crate::prelude::AuthProvider;
+
+pub type BoxedAuthProvider = Box<dyn AuthProvider + Send + Sync>;
+
+type Factory = Box<dyn Fn(&ProviderOptions) -> crate::error::Result<BoxedAuthProvider> + Send + Sync>;
+
+/// Provider-specific options as key-value pairs, e.g. read from a config file. Every provider
+/// rejects options it does not know.
+///
+/// | provider | option | values |
+/// |----------|--------|--------|
+/// | `global` | `restartup_policy` | `return_error`, `fallback_to_steam` |
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProviderOptions {
+    options: HashMap<String, String>,
+}
+
+impl ProviderOptions {
+    pub fn with<K, V>(mut self, key: K, value: V) -> Self
+    where K: Into<String>, V: Into<String> {
+        self.options.insert(key.into(), value.into());
+        self
+    }
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
+
+    /// Returns [`Error::InvalidProviderOption`] for the first option not in `known`.
+    pub fn expect_only(&self, provider: &str, known: &[&str]) -> crate::error::Result<()> {
+        match self.options.keys().find(|key| !known.contains(&key.as_str())) {
+            Some(key) => Err(Error::InvalidProviderOption(format!("{provider} has no option {key}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for ProviderOptions
+where K: Into<String>, V: Into<String> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        ProviderOptions {
+            options: iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+        }
+    }
+}
+
+/// Builds providers by name, so the login method can come from configuration.
+///
+/// [`ProviderRegistry::default`] knows `global`, `steam`, `kr` and `cn`. Names whose feature was
+/// not compiled in return [`Error::ProviderNotCompiled`]. Custom providers and layered stacks can
+/// be added with [`ProviderRegistry::register`], replacing a built-in of the same name.
+///
+/// ```no_run
+/// # use ff_auth::prelude::*;
+/// let options = ProviderOptions::default().with("restartup_policy", "fallback_to_steam");
+/// let provider = ProviderRegistry::default().build("global", &options)?;
+/// # Ok::<(), Error>(())
+/// ```
+pub struct ProviderRegistry {
+    factories: HashMap<String, Factory>,
+}
+
+impl Debug for ProviderRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProviderRegistry")
+            .field("providers", &self.factories.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = ProviderRegistry::empty();
+        registry.register("global", global);
+        registry.register("steam", steam);
+        registry.register("kr", |_: &ProviderOptions| not_implemented("kr", cfg!(feature = "kr")));
+        registry.register("cn", |_: &ProviderOptions| not_implemented("cn", cfg!(feature = "cn")));
+        registry
+    }
+}
+
+impl ProviderRegistry {
+    /// A registry without the built-in providers.
+    pub fn empty() -> Self {
+        ProviderRegistry { factories: HashMap::new() }
+    }
+
+    pub fn register<N, F>(&mut self, name: N, factory: F)
+    where N: Into<String>, F: Fn(&ProviderOptions) -> crate::error::Result<BoxedAuthProvider> + Send + Sync + 'static {
+        self.factories.insert(name.into(), Box::new(factory));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    pub fn build(&self, name: &str, options: &ProviderOptions) -> crate::error::Result<BoxedAuthProvider> {
+        let factory = self.factories.get(name)
+            .ok_or_else(|| Error::UnknownProvider(name.to_string()))?;
+        factory(options)
+    }
+}
+
+#[cfg(feature = "global")]
+fn global(options: &ProviderOptions) -> crate::error::Result<BoxedAuthProvider> {
+    use crate::clients::{GlobalClient, RestartupPolicy};
+
+    options.expect_only("global", &["restartup_policy"])?;
+    let policy = match options.get("restartup_policy") {
+        None | Some("return_error") => RestartupPolicy::ReturnError,
+        Some("fallback_to_steam") => RestartupPolicy::FallbackToSteam,
+        Some(other) => return Err(Error::InvalidProviderOption(format!("unknown restartup_policy {other}"))),
+    };
+    Ok(Box::new(GlobalClient::default().with_restartup_policy(policy)))
+}
+
+#[cfg(not(feature = "global"))]
+fn global(_options: &ProviderOptions) -> crate::error::Result<BoxedAuthProvider> {
+    Err(Error::ProviderNotCompiled { provider: "global", feature: "global" })
+}
+
+#[cfg(feature = "steam_shared")]
+fn steam(options: &ProviderOptions) -> crate::error::Result<BoxedAuthProvider> {
+    options.expect_only("steam", &[])?;
+    Ok(Box::new(crate::clients::SteamClient::default()))
+}
+
+#[cfg(not(feature = "steam_shared"))]
+fn steam(_options: &ProviderOptions) -> crate::error::Result<BoxedAuthProvider> {
+    Err(Error::ProviderNotCompiled { provider: "steam", feature: "steam` or `steam_dynamic" })
+}
+
+/// The KR and CN regions have features but no clients yet.
+fn not_implemented(provider: &'static str, compiled: bool) -> crate::error::Result<BoxedAuthProvider> {
+    if compiled {
+        Err(Error::ProviderNotImplemented(provider))
+    } else {
+        Err(Error::ProviderNotCompiled { provider, feature: provider })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let registry = ProviderRegistry::default();
+        assert!(matches!(registry.build("jp", &ProviderOptions::default()), Err(Error::UnknownProvider(_))));
+
+        #[cfg(feature = "global")]
+        {
+            let options = ProviderOptions::default().with("restartup_policy", "fallback_to_steam");
+            assert!(registry.build("global", &options).is_ok());
+            let options = ProviderOptions::default().with("region", "3");
+            assert!(matches!(registry.build("global", &options), Err(Error::InvalidProviderOption(_))));
+        }
+    }
+}
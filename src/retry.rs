@@ -0,0 +1,258 @@
+use std::time::Duration;
+use rand::Rng;
+use reqwest::{header, StatusCode};
+use tracing::warn;
+use crate::error::Error;
+use crate::prelude::{AuthLayer, AuthProvider, LoginRequest, LoginResponse, TypedAuthProvider};
+use crate::transport::{HttpRequest, HttpResponse, HttpTransport, TransportErrorKind};
+
+/// How often the HTTP requests of a login are retried after network errors and server errors.
+///
+/// Fetching the login page is retried after any network error, `429 Too Many Requests` and `5xx`.
+/// Submitting the login form is only retried when it cannot have reached the login server: when
+/// connecting failed, or on `429` and `503 Service Unavailable`. A rejected login is never
+/// retried, repeated failed logins lock Square Enix accounts. A `Retry-After` longer than
+/// `max_backoff` gives up instead of waiting that long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per request including the first one, `1` disables retries.
+    pub max_attempts: u32,
+    /// The wait before the first retry, doubled for every further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Randomizes each wait between half and all of the backoff.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+impl RetryPolicy {
+    pub const fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+        }
+    }
+    pub fn exponential(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            ..RetryPolicy::none()
+        }
+    }
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+    pub fn without_jitter(mut self) -> Self {
+        self.jitter = false;
+        self
+    }
+
+    /// The wait before retry number `retry`, counting from 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+        if self.jitter {
+            backoff / 2 + rand::thread_rng().gen_range(Duration::ZERO..=backoff / 2)
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Applies a [`RetryPolicy`] to the requests that keep the default one, which does not retry.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryLayer {
+    policy: RetryPolicy,
+}
+
+impl RetryLayer {
+    pub fn new(policy: RetryPolicy) -> Self {
+        RetryLayer { policy }
+    }
+}
+
+impl<P> AuthLayer<P> for RetryLayer {
+    type Provider = RetryingProvider<P>;
+
+    fn layer(&self, inner: P) -> Self::Provider {
+        RetryingProvider { inner, policy: self.policy }
+    }
+}
+
+pub struct RetryingProvider<P> {
+    inner: P,
+    policy: RetryPolicy,
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for RetryingProvider<P>
+where P: AuthProvider + Send + Sync {
+    async fn authenticate(&self, mut req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        if req.retry_policy == RetryPolicy::none() {
+            req.retry_policy = self.policy;
+        }
+        self.inner.authenticate(req).await
+    }
+}
+
+impl<P> TypedAuthProvider for RetryingProvider<P>
+where P: TypedAuthProvider + Send + Sync {
+    type Credentials = P::Credentials;
+}
+
+/// Sends `request` through `transport` until it succeeds or `policy` gives up. Only `idempotent`
+/// requests are retried after errors the server may already have acted on.
+pub(crate) async fn send_with_retry(transport: &dyn HttpTransport, policy: &RetryPolicy, idempotent: bool, request: HttpRequest) -> crate::error::Result<HttpResponse> {
+    let mut attempt = 1;
+    loop {
+        let res = transport.send(request.clone()).await;
+        let retry = match &res {
+            Ok(response) if is_retryable_status(response.status, idempotent) => Some(retry_after(response)),
+            Err(e) if is_retryable_error(e, idempotent) => Some(None),
+            _ => None,
+        };
+
+        match retry {
+            Some(Some(retry_after)) if retry_after > policy.max_backoff => {
+                warn!(?retry_after, "Not retrying, the server asks to wait longer than the maximum backoff");
+                return res?.error_for_status();
+            }
+            Some(retry_after) if attempt < policy.max_attempts => {
+                // a Retry-After from the server wins over a shorter backoff
+                let delay = policy.backoff(attempt).max(retry_after.unwrap_or_default());
+                warn!(attempt, ?delay, "Retrying request");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            _ => return res?.error_for_status(),
+        }
+    }
+}
+
+fn is_retryable_status(status: StatusCode, idempotent: bool) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::SERVICE_UNAVAILABLE
+        || (idempotent && status.is_server_error())
+}
+
+fn is_retryable_error(e: &Error, idempotent: bool) -> bool {
+    // nothing was sent when connecting failed
+    match e {
+        Error::Reqwest(e) => e.is_connect() || (idempotent && (e.is_timeout() || e.is_request())),
+        Error::Transport { kind, .. } => *kind == TransportErrorKind::Connect || (idempotent && *kind == TransportErrorKind::Timeout),
+        _ => false,
+    }
+}
+
+/// The `Retry-After` header in seconds. HTTP dates are not supported.
+fn retry_after(response: &HttpResponse) -> Option<Duration> {
+    let value = response.headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use reqwest::Url;
+    use crate::transport::MemoryTransport;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::exponential(max_attempts)
+            .with_backoff(Duration::from_millis(1), Duration::from_secs(10))
+            .without_jitter()
+    }
+
+    fn status(code: u16) -> HttpResponse {
+        HttpResponse::new(StatusCode::from_u16(code).unwrap(), "")
+    }
+
+    async fn send(transport: &MemoryTransport, policy: RetryPolicy, idempotent: bool) -> crate::error::Result<HttpResponse> {
+        let url = Url::parse("https://ffxiv-login.square-enix.com/oauth/ffxivarr/login/top").unwrap();
+        let request = if idempotent { HttpRequest::get(url) } else { HttpRequest::post(url) };
+        send_with_retry(transport, &policy, idempotent, request).await
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::exponential(5)
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(3))
+            .without_jitter();
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(3));
+
+        let policy = RetryPolicy::exponential(5).with_backoff(Duration::from_secs(4), Duration::from_secs(4));
+        for _ in 0..10 {
+            let backoff = policy.backoff(1);
+            assert!(backoff >= Duration::from_secs(2) && backoff <= Duration::from_secs(4));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_get() {
+        let transport = MemoryTransport::new();
+        transport.push_response(status(500));
+        transport.push_response(status(503));
+        transport.push_response(status(429));
+        transport.push_error(TransportErrorKind::Timeout);
+        transport.push_page("form");
+        assert_eq!(send(&transport, policy(5), true).await.unwrap().text(), "form");
+        assert_eq!(transport.requests().len(), 5);
+
+        let transport = MemoryTransport::new();
+        for _ in 0..3 {
+            transport.push_response(status(500));
+        }
+        assert!(matches!(send(&transport, policy(2), true).await, Err(Error::HttpStatus(StatusCode::INTERNAL_SERVER_ERROR))));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_post() {
+        // the server may have seen the credentials, so these are never sent twice
+        let transport = MemoryTransport::new();
+        transport.push_response(status(500));
+        assert!(matches!(send(&transport, policy(5), false).await, Err(Error::HttpStatus(StatusCode::INTERNAL_SERVER_ERROR))));
+        assert_eq!(transport.requests().len(), 1);
+
+        let transport = MemoryTransport::new();
+        transport.push_error(TransportErrorKind::Timeout);
+        assert!(matches!(send(&transport, policy(5), false).await, Err(Error::Transport { kind: TransportErrorKind::Timeout, .. })));
+        assert_eq!(transport.requests().len(), 1);
+
+        // the login server did not act on these
+        let transport = MemoryTransport::new();
+        transport.push_response(status(503));
+        transport.push_response(status(429));
+        transport.push_error(TransportErrorKind::Connect);
+        transport.push_page("result");
+        assert_eq!(send(&transport, policy(5), false).await.unwrap().text(), "result");
+        assert_eq!(transport.requests().len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_after() {
+        let transport = MemoryTransport::new();
+        transport.push_response(status(429).header(header::RETRY_AFTER, HeaderValue::from_static("5")));
+        transport.push_page("form");
+        let start = tokio::time::Instant::now();
+        send(&transport, policy(3), true).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(5));
+
+        let transport = MemoryTransport::new();
+        transport.push_response(status(429).header(header::RETRY_AFTER, HeaderValue::from_static("3600")));
+        transport.push_page("form");
+        assert!(matches!(send(&transport, policy(3), true).await, Err(Error::HttpStatus(StatusCode::TOO_MANY_REQUESTS))));
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::time::Duration;
+pub use tokio_util::sync::CancellationToken;
+use crate::error::Error;
+use crate::prelude::LoginRequest;
+
+/// The parts of a login that can time out or be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStage {
+    /// Fetching the login form.
+    LoginPage,
+    /// Initializing Steam and creating the session ticket.
+    SteamTicket,
+    /// Submitting the login form, including asking a credential provider for the password.
+    Submit,
+    /// Waiting for the OTP provider.
+    Otp,
+}
+
+impl Display for LoginStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LoginStage::LoginPage => "fetching the login page",
+            LoginStage::SteamTicket => "creating the Steam ticket",
+            LoginStage::Submit => "submitting the login form",
+            LoginStage::Otp => "waiting for the one-time password",
+        })
+    }
+}
+
+/// How long each stage of a login may take. Stages without a timeout are only bounded by the
+/// HTTP client's own timeouts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTimeouts {
+    pub login_page: Option<Duration>,
+    pub steam_ticket: Option<Duration>,
+    pub submit: Option<Duration>,
+    pub otp: Option<Duration>,
+}
+
+impl StageTimeouts {
+    pub fn with_login_page(mut self, timeout: Duration) -> Self {
+        self.login_page = Some(timeout);
+        self
+    }
+    /// Steam cannot be interrupted, a timed out Steam initialization keeps running in the background.
+    pub fn with_steam_ticket(mut self, timeout: Duration) -> Self {
+        self.steam_ticket = Some(timeout);
+        self
+    }
+    pub fn with_submit(mut self, timeout: Duration) -> Self {
+        self.submit = Some(timeout);
+        self
+    }
+    pub fn with_otp(mut self, timeout: Duration) -> Self {
+        self.otp = Some(timeout);
+        self
+    }
+
+    pub fn get(&self, stage: LoginStage) -> Option<Duration> {
+        match stage {
+            LoginStage::LoginPage => self.login_page,
+            LoginStage::SteamTicket => self.steam_ticket,
+            LoginStage::Submit => self.submit,
+            LoginStage::Otp => self.otp,
+        }
+    }
+}
+
+/// Runs `fut` as `stage` of the login `req`, bounded by its timeout and cancellation token.
+pub(crate) async fn bounded<T, F>(req: &LoginRequest, stage: LoginStage, fut: F) -> crate::error::Result<T>
+where F: Future<Output = crate::error::Result<T>> {
+    let timeout = req.timeouts.get(stage);
+    let fut = async {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, fut).await.map_err(|_| Error::Timeout(stage))?,
+            None => fut.await,
+        }
+    };
+
+    match &req.cancellation {
+        Some(token) => token.run_until_cancelled(fut).await.unwrap_or(Err(Error::Cancelled(stage))),
+        None => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bounded() {
+        let pending = || std::future::pending::<crate::error::Result<()>>();
+
+        let req = LoginRequest::new(reqwest::Client::new())
+            .with_timeouts(StageTimeouts::default().with_submit(Duration::from_millis(10)));
+        let res = bounded(&req, LoginStage::Submit, pending()).await;
+        assert!(matches!(res, Err(Error::Timeout(LoginStage::Submit))));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let req = LoginRequest::new(reqwest::Client::new()).with_cancellation(token);
+        let res = bounded(&req, LoginStage::LoginPage, pending()).await;
+        assert!(matches!(res, Err(Error::Cancelled(LoginStage::LoginPage))));
+    }
+}
@@ -1,25 +1,64 @@
 use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::cookies::CookieJar;
+use crate::credentials::CredentialProvider;
+use crate::events::AuthEventHandler;
+use crate::fingerprint::{LauncherFingerprint, UserAgent};
+use crate::identity::ComputerId;
+use crate::otp::OtpProvider;
+use crate::protocol::OAuthProtocol;
+use crate::retry::RetryPolicy;
+use crate::timeouts::{CancellationToken, StageTimeouts};
+use crate::transport::HttpTransport;
 
 #[derive(Clone)]
 pub struct LoginRequest {
-    pub client: reqwest::Client,
+    /// Sends the HTTP requests, usually a [`reqwest::Client`].
+    pub transport: Arc<dyn HttpTransport>,
 
     pub username: Option<String>,
     pub password: Option<secure_string::SecureString>,
     pub otp: Option<String>,
     pub region: Option<i8>,
     pub is_free_trial: Option<bool>,
+    /// The computer ID sent in the user agent, [`ComputerId::for_machine`] when not set.
+    pub computer_id: Option<ComputerId>,
+    /// The launcher the requests imitate.
+    pub fingerprint: LauncherFingerprint,
+    /// The protocol parameters of the login page URL.
+    pub protocol: OAuthProtocol,
+    /// Asked for the one-time password right before submitting when `otp` is not set.
+    pub otp_provider: Option<Arc<dyn OtpProvider>>,
+    /// Asked for the password right before submitting when `password` is not set.
+    pub credential_provider: Option<Arc<dyn CredentialProvider>>,
+    /// Told about the progress of the login.
+    pub event_handler: Option<Arc<dyn AuthEventHandler>>,
+    pub timeouts: StageTimeouts,
+    pub retry_policy: RetryPolicy,
+    /// Cancelling the token aborts the login at the stage it is in.
+    pub cancellation: Option<CancellationToken>,
 }
 
 impl LoginRequest {
-    pub fn new(client: reqwest::Client) -> Self {
+    pub fn new<T>(transport: T) -> Self
+    where T: HttpTransport + 'static {
         LoginRequest {
-            client,
+            transport: Arc::new(transport),
             username: None,
             password: None,
             otp: None,
             region: None,
             is_free_trial: None,
+            computer_id: None,
+            fingerprint: LauncherFingerprint::default(),
+            protocol: OAuthProtocol::default(),
+            otp_provider: None,
+            credential_provider: None,
+            event_handler: None,
+            timeouts: StageTimeouts::default(),
+            retry_policy: RetryPolicy::default(),
+            cancellation: None,
         }
     }
     pub fn with_username(mut self, username: String) -> Self {
@@ -31,25 +70,253 @@ impl LoginRequest {
         self.password = Some(secure_string::SecureString::from(password));
         self
     }
+    pub fn with_credential_provider<P>(mut self, provider: P) -> Self
+    where P: CredentialProvider + 'static {
+        self.credential_provider = Some(Arc::new(provider));
+        self
+    }
     pub fn with_otp(mut self, otp: String) -> Self {
         self.otp = Some(otp);
         self
     }
+    pub fn with_otp_provider<P>(mut self, provider: P) -> Self
+    where P: OtpProvider + 'static {
+        self.otp_provider = Some(Arc::new(provider));
+        self
+    }
+    pub fn with_event_handler<H>(mut self, handler: H) -> Self
+    where H: AuthEventHandler + 'static {
+        self.event_handler = Some(Arc::new(handler));
+        self
+    }
+    pub fn with_timeouts(mut self, timeouts: StageTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
     pub fn with_region(mut self, region: i8) -> Self {
         self.region = Some(region);
         self
     }
+    pub fn with_computer_id(mut self, computer_id: ComputerId) -> Self {
+        self.computer_id = Some(computer_id);
+        self
+    }
+    pub fn with_fingerprint(mut self, fingerprint: LauncherFingerprint) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+    /// Sets the platform and locale of the fingerprint's user agent.
+    pub fn with_user_agent(mut self, user_agent: UserAgent) -> Self {
+        self.fingerprint.user_agent = user_agent;
+        self
+    }
+    pub fn with_protocol(mut self, protocol: OAuthProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
     pub fn with_free_trial(mut self) -> Self {
         self.is_free_trial = Some(true);
         self
     }
+    /// Forces the full game login. Steam logins detect the owned app when neither this nor
+    /// [`LoginRequest::with_free_trial`] is set.
+    pub fn without_free_trial(mut self) -> Self {
+        self.is_free_trial = Some(false);
+        self
+    }
+
+    /// The Square Enix ID set on the request or known to its credential provider.
+    pub(crate) fn account_name(&self) -> Option<String> {
+        self.username.clone()
+            .or_else(|| self.credential_provider.as_ref().and_then(|p| p.username()))
+    }
+
+    /// The password set on the request, or else the one of the credential provider.
+    pub(crate) async fn resolve_password(&self) -> crate::error::Result<Option<secure_string::SecureString>> {
+        match (&self.password, &self.credential_provider) {
+            (Some(password), _) => Ok(Some(password.clone())),
+            (None, Some(provider)) => Ok(Some(provider.password().await?)),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl LoginRequest {
+    /// Starts a [`LoginRequestBuilder`] that tracks the credentials in its type, see
+    /// [`TypedAuthProvider::login`].
+    pub fn builder<T>(transport: T) -> LoginRequestBuilder<NoCredentials>
+    where T: HttpTransport + 'static {
+        LoginRequestBuilder {
+            request: LoginRequest::new(transport),
+            credentials: NoCredentials,
+        }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The credentials a [`LoginRequestBuilder`] carries.
+pub trait CredentialState: private::Sealed {
+    #[doc(hidden)]
+    fn apply(self, req: &mut LoginRequest);
+}
+
+/// The request has no credentials, as used by Steam logins.
+#[derive(Debug, Clone)]
+pub struct NoCredentials;
+
+/// A Square Enix ID and a password or a [`CredentialProvider`] for it.
+#[derive(Clone)]
+pub struct Credentials {
+    username: String,
+    password: CredentialSource,
+}
+
+#[derive(Clone)]
+enum CredentialSource {
+    Password(secure_string::SecureString),
+    Provider(Arc<dyn CredentialProvider>),
+}
+
+impl private::Sealed for NoCredentials {}
+impl private::Sealed for Credentials {}
+
+impl CredentialState for NoCredentials {
+    fn apply(self, _req: &mut LoginRequest) {}
+}
+
+impl CredentialState for Credentials {
+    fn apply(self, req: &mut LoginRequest) {
+        req.username = Some(self.username);
+        match self.password {
+            CredentialSource::Password(password) => req.password = Some(password),
+            CredentialSource::Provider(provider) => req.credential_provider = Some(provider),
+        }
+    }
+}
+
+/// A [`LoginRequest`] builder that only lets providers accept requests with the credentials they need.
+#[derive(Clone)]
+pub struct LoginRequestBuilder<C> {
+    request: LoginRequest,
+    credentials: C,
+}
+
+impl LoginRequestBuilder<NoCredentials> {
+    pub fn credentials<U>(self, username: String, password: U) -> LoginRequestBuilder<Credentials>
+    where U: Into<String> {
+        LoginRequestBuilder {
+            request: self.request,
+            credentials: Credentials {
+                username,
+                password: CredentialSource::Password(secure_string::SecureString::from(password)),
+            },
+        }
+    }
+    /// Like [`LoginRequestBuilder::credentials`], with the password asked from `provider` at submit time.
+    pub fn credential_provider<P>(self, username: String, provider: P) -> LoginRequestBuilder<Credentials>
+    where P: CredentialProvider + 'static {
+        LoginRequestBuilder {
+            request: self.request,
+            credentials: Credentials {
+                username,
+                password: CredentialSource::Provider(Arc::new(provider)),
+            },
+        }
+    }
+}
+
+impl<C: CredentialState> LoginRequestBuilder<C> {
+    pub fn with_otp(mut self, otp: String) -> Self {
+        self.request = self.request.with_otp(otp);
+        self
+    }
+    pub fn with_otp_provider<P>(mut self, provider: P) -> Self
+    where P: OtpProvider + 'static {
+        self.request = self.request.with_otp_provider(provider);
+        self
+    }
+    pub fn with_event_handler<H>(mut self, handler: H) -> Self
+    where H: AuthEventHandler + 'static {
+        self.request = self.request.with_event_handler(handler);
+        self
+    }
+    pub fn with_timeouts(mut self, timeouts: StageTimeouts) -> Self {
+        self.request = self.request.with_timeouts(timeouts);
+        self
+    }
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.request = self.request.with_cancellation(token);
+        self
+    }
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.request = self.request.with_retry_policy(policy);
+        self
+    }
+    pub fn with_region(mut self, region: i8) -> Self {
+        self.request = self.request.with_region(region);
+        self
+    }
+    pub fn with_computer_id(mut self, computer_id: ComputerId) -> Self {
+        self.request = self.request.with_computer_id(computer_id);
+        self
+    }
+    pub fn with_fingerprint(mut self, fingerprint: LauncherFingerprint) -> Self {
+        self.request = self.request.with_fingerprint(fingerprint);
+        self
+    }
+    pub fn with_user_agent(mut self, user_agent: UserAgent) -> Self {
+        self.request = self.request.with_user_agent(user_agent);
+        self
+    }
+    pub fn with_protocol(mut self, protocol: OAuthProtocol) -> Self {
+        self.request = self.request.with_protocol(protocol);
+        self
+    }
+    pub fn with_free_trial(mut self) -> Self {
+        self.request = self.request.with_free_trial();
+        self
+    }
+    pub fn without_free_trial(mut self) -> Self {
+        self.request = self.request.without_free_trial();
+        self
+    }
+    pub fn build(self) -> LoginRequest {
+        let mut request = self.request;
+        self.credentials.apply(&mut request);
+        request
+    }
+}
+
+impl<C: CredentialState> From<LoginRequestBuilder<C>> for LoginRequest {
+    fn from(builder: LoginRequestBuilder<C>) -> Self {
+        builder.build()
+    }
 }
 
+impl<C> Debug for LoginRequestBuilder<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginRequestBuilder")
+            .field("request", &self.request)
+            .field("credentials", &std::any::type_name::<C>().rsplit("::").next())
+            .finish()
+    }
+}
 
 impl Debug for LoginRequest {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("LoginRequest")
-            .field("client", &"[Client]")
+            .field("transport", &"[HttpTransport]")
             .field("username", &self.username.as_ref().map(|u| {
                 if u.len() <= 3 {
                     u.clone()
@@ -59,21 +326,134 @@ impl Debug for LoginRequest {
             }))
             .field("password", &self.password.as_ref().map(|_| "[REDACTED]"))
             .field("otp", &self.otp.as_ref().map(|_| "[REDACTED]"))
+            .field("otp_provider", &self.otp_provider.as_ref().map(|_| "[OtpProvider]"))
+            .field("credential_provider", &self.credential_provider.as_ref().map(|_| "[CredentialProvider]"))
+            .field("event_handler", &self.event_handler.as_ref().map(|_| "[AuthEventHandler]"))
+            .field("timeouts", &self.timeouts)
+            .field("retry_policy", &self.retry_policy)
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+/// The login flow that produced a [`LoginResponse`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoginPath {
+    /// Square Enix ID and password login.
+    #[default]
+    Global,
+    /// Steam session ticket login.
+    Steam,
+}
+
+/// The Steam account a Steam login used.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SteamIdentity {
+    pub(crate) steam_id: u64,
+    pub(crate) persona_name: String,
+    pub(crate) app_id: u32,
+}
+
+impl SteamIdentity {
+    /// The SteamID64 of the account.
+    pub fn steam_id(&self) -> u64 {
+        self.steam_id
+    }
+    pub fn persona_name(&self) -> &str {
+        &self.persona_name
+    }
+    /// The Steam app the session ticket was created for, either the full game or the free trial.
+    pub fn app_id(&self) -> u32 {
+        self.app_id
+    }
+}
+
+impl Debug for SteamIdentity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let steam_id = self.steam_id.to_string();
+        f.debug_struct("SteamIdentity")
+            .field("steam_id", &format!("{}***", steam_id.chars().take(7).collect::<String>()))
+            .field("persona_name", &self.persona_name)
+            .field("app_id", &self.app_id)
             .finish()
     }
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub(crate) session_id: String,
     pub(crate) region: u8,
     pub(crate) terms_accepted: bool,
     pub(crate) playable: bool,
     pub(crate) max_expansion: u8,
+    pub(crate) login_path: LoginPath,
+    pub(crate) steam_identity: Option<SteamIdentity>,
+    #[serde(default)]
+    pub(crate) cookies: CookieJar,
+}
+
+impl LoginResponse {
+    /// Which login flow succeeded.
+    pub fn login_path(&self) -> LoginPath {
+        self.login_path
+    }
+    /// The Steam account used by a Steam login.
+    pub fn steam_identity(&self) -> Option<&SteamIdentity> {
+        self.steam_identity.as_ref()
+    }
+    /// The cookies of the login, for follow-up requests to the login site.
+    pub fn cookies(&self) -> &CookieJar {
+        &self.cookies
+    }
 }
 
 #[async_trait::async_trait]
 pub trait AuthProvider {
     async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse>;
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for Box<P>
+where P: AuthProvider + Send + Sync + ?Sized {
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        (**self).authenticate(req).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<P> AuthProvider for std::sync::Arc<P>
+where P: AuthProvider + Send + Sync + ?Sized {
+    async fn authenticate(&self, req: LoginRequest) -> crate::error::Result<LoginResponse> {
+        (**self).authenticate(req).await
+    }
+}
+
+/// An [`AuthProvider`] that states which credentials it needs, so a request without them does
+/// not compile.
+///
+/// ```no_run
+/// use ff_auth::prelude::*;
+///
+/// async fn auth(client: reqwest::Client) -> Result<LoginResponse, Error> {
+///     let req = LoginRequest::builder(client).credentials("username".to_string(), "password");
+///     GlobalClient::default().login(req).await
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use ff_auth::prelude::*;
+///
+/// async fn auth(client: reqwest::Client) {
+///     // GlobalClient needs a Square Enix ID and password
+///     GlobalClient::default().login(LoginRequest::builder(client)).await;
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait TypedAuthProvider: AuthProvider + Sync {
+    type Credentials: CredentialState + Send;
+
+    async fn login(&self, req: LoginRequestBuilder<Self::Credentials>) -> crate::error::Result<LoginResponse> {
+        self.authenticate(req.build()).await
+    }
 }
\ No newline at end of file
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode, Url};
+use crate::cookies::CookieJar;
+use crate::error::Error;
+
+/// An HTTP request of a login, independent of the HTTP client that sends it.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+}
+
+impl HttpRequest {
+    pub fn new(method: Method, url: Url) -> Self {
+        HttpRequest {
+            method,
+            url,
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+    pub fn get(url: Url) -> Self {
+        HttpRequest::new(Method::GET, url)
+    }
+    pub fn post(url: Url) -> Self {
+        HttpRequest::new(Method::POST, url)
+    }
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+    /// Adds the `Cookie` header of `jar` for the request URL, if it has matching cookies.
+    pub fn with_cookies(self, jar: &CookieJar) -> Self {
+        match jar.header_for(&self.url) {
+            Some(cookies) => self.header(header::COOKIE, cookies),
+            None => self,
+        }
+    }
+    pub fn body<B>(mut self, body: B) -> Self
+    where B: Into<Vec<u8>> {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new<B>(status: StatusCode, body: B) -> Self
+    where B: Into<Vec<u8>> {
+        HttpResponse {
+            status,
+            headers: HeaderMap::new(),
+            body: body.into(),
+        }
+    }
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// The body as text, replacing invalid UTF-8.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Turns `4xx` and `5xx` answers into [`Error::HttpStatus`].
+    pub fn error_for_status(self) -> crate::error::Result<Self> {
+        if self.status.is_client_error() || self.status.is_server_error() {
+            Err(Error::HttpStatus(self.status))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Why a transport could not get an answer, used to decide whether a request is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportErrorKind {
+    /// Nothing was sent.
+    Connect,
+    Timeout,
+    Other,
+}
+
+impl Display for TransportErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TransportErrorKind::Connect => "connect",
+            TransportErrorKind::Timeout => "timeout",
+            TransportErrorKind::Other => "other",
+        })
+    }
+}
+
+/// Sends the HTTP requests of a login. Implemented for [`reqwest::Client`], and by
+/// [`MemoryTransport`] for tests. Other implementations report their failures as
+/// [`Error::Transport`].
+#[async_trait::async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> crate::error::Result<HttpResponse>;
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for reqwest::Client {
+    async fn send(&self, request: HttpRequest) -> crate::error::Result<HttpResponse> {
+        let mut builder = self.request(request.method, request.url).headers(request.headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send().await?;
+        Ok(HttpResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.bytes().await?.to_vec(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> HttpTransport for Arc<T>
+where T: HttpTransport + ?Sized {
+    async fn send(&self, request: HttpRequest) -> crate::error::Result<HttpResponse> {
+        (**self).send(request).await
+    }
+}
+
+/// Answers requests with queued responses in order and records the requests, for testing code
+/// that logs in without a network. Clones share the queue.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    responses: VecDeque<Result<HttpResponse, TransportErrorKind>>,
+    requests: Vec<HttpRequest>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        MemoryTransport::default()
+    }
+    pub fn push_response(&self, response: HttpResponse) {
+        self.state.lock().unwrap().responses.push_back(Ok(response));
+    }
+    /// Queues a `200 OK` with `body`.
+    pub fn push_page<B>(&self, body: B)
+    where B: Into<Vec<u8>> {
+        self.push_response(HttpResponse::new(StatusCode::OK, body));
+    }
+    pub fn push_error(&self, kind: TransportErrorKind) {
+        self.state.lock().unwrap().responses.push_back(Err(kind));
+    }
+    /// The requests sent so far.
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for MemoryTransport {
+    async fn send(&self, request: HttpRequest) -> crate::error::Result<HttpResponse> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(request);
+        match state.responses.pop_front() {
+            Some(Ok(response)) => Ok(response),
+            Some(Err(kind)) => Err(Error::Transport { kind, message: "injected error".to_string() }),
+            None => Err(Error::Transport { kind: TransportErrorKind::Other, message: "no response queued".to_string() }),
+        }
+    }
+}
+
+/// Builds a [`reqwest::Client`] set up for the login servers: a cookie store, gzip and deflate,
+/// and connect and request timeouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpClientConfig {
+    pub connect_timeout: Duration,
+    /// The timeout of each request, from connecting until the body was read.
+    pub timeout: Duration,
+    /// Keeps cookies the server sets for later requests of the client. Logins send the cookies
+    /// of their own [`CookieJar`] instead.
+    pub cookies: bool,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        HttpClientConfig {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            cookies: true,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    pub fn without_cookies(mut self) -> Self {
+        self.cookies = false;
+        self
+    }
+
+    pub fn build(&self) -> crate::error::Result<reqwest::Client> {
+        Ok(reqwest::Client::builder()
+            .cookie_store(self.cookies)
+            .gzip(true)
+            .deflate(true)
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .build()?)
+    }
+}